@@ -0,0 +1,188 @@
+use crate::error::{Error, Result};
+use reqwest::header::HeaderMap;
+
+/// 识别出的拦截页面类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Block {
+    /// Google "unusual traffic" 验证页面、reCAPTCHA、hCaptcha 等
+    Captcha,
+    /// Cloudflare 的浏览器检查或拦截页面
+    Cloudflare,
+    /// Yandex SmartCaptcha
+    SmartCaptcha,
+    /// 服务维护中
+    Maintenance,
+    /// 成功状态码但正文为空，通常是被静默拦截
+    Empty,
+}
+
+impl Block {
+    /// 拦截原因的说明
+    pub fn reason(self) -> &'static str {
+        match self {
+            Block::Captcha | Block::SmartCaptcha => "需要人机验证",
+            Block::Cloudflare => "Cloudflare 拦截",
+            Block::Maintenance => "服务正在维护中",
+            Block::Empty => "响应为空",
+        }
+    }
+
+    /// 转换为带引擎信息的错误
+    pub fn into_error(self, engine: &'static str) -> Error {
+        match self {
+            Block::Captcha | Block::SmartCaptcha => Error::Captcha { engine },
+            _ => Error::Blocked {
+                engine,
+                reason: self.reason().to_string(),
+            },
+        }
+    }
+}
+
+/// 页面正文中的特征文本
+const CAPTCHA_MARKERS: &[&str] = &[
+    "Our systems have detected unusual traffic",
+    "id=\"captcha-form\"",
+    "class=\"g-recaptcha\"",
+    "class=\"h-captcha\"",
+];
+
+/// 不包含 `/cdn-cgi/challenge-platform/`：Cloudflare 的 bot 管理脚本也会从该路径加载，
+/// 并注入到正常的结果页中
+const CLOUDFLARE_MARKERS: &[&str] = &[
+    "cf-browser-verification",
+    "window._cf_chl_opt",
+    "<title>Just a moment...</title>",
+    "<title>Attention Required! | Cloudflare</title>",
+];
+
+/// 只匹配验证页面本身的表单与跳转地址；正常的结果页也可能加载 SmartCaptcha 脚本
+const SMART_CAPTCHA_MARKERS: &[&str] = &["checkbox-captcha-form", "/showcaptcha"];
+
+const MAINTENANCE_MARKERS: &[&str] = &["The service is under construction"];
+
+/// 检查响应是否为拦截页面
+///
+/// 在解析之前调用，避免把拦截页面当作“没有结果”
+pub fn detect(url: &str, status: u16, headers: &HeaderMap, body: &str) -> Option<Block> {
+    // Google 与 Yandex 会把被拦截的请求重定向到验证页面
+    if url.contains("google.com/sorry/") {
+        return Some(Block::Captcha);
+    }
+    if url.contains("/showcaptcha") {
+        return Some(Block::SmartCaptcha);
+    }
+    if headers
+        .get("cf-mitigated")
+        .is_some_and(|value| value == "challenge")
+    {
+        return Some(Block::Cloudflare);
+    }
+
+    let contains_any = |markers: &[&str]| markers.iter().any(|marker| body.contains(marker));
+    if contains_any(CLOUDFLARE_MARKERS) {
+        Some(Block::Cloudflare)
+    } else if contains_any(SMART_CAPTCHA_MARKERS) {
+        Some(Block::SmartCaptcha)
+    } else if contains_any(CAPTCHA_MARKERS) {
+        Some(Block::Captcha)
+    } else if contains_any(MAINTENANCE_MARKERS) {
+        Some(Block::Maintenance)
+    } else if (200..300).contains(&status) && body.trim().is_empty() {
+        Some(Block::Empty)
+    } else {
+        None
+    }
+}
+
+/// 检查响应并转换为错误，未被拦截时返回 `Ok(())`
+pub fn check(
+    engine: &'static str,
+    url: &str,
+    status: u16,
+    headers: &HeaderMap,
+    body: &str,
+) -> Result<()> {
+    match detect(url, status, headers, body) {
+        Some(block) => Err(block.into_error(engine)),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_block_pages() {
+        let headers = HeaderMap::new();
+        let detect = |url: &str, status: u16, body: &str| detect(url, status, &headers, body);
+
+        assert_eq!(
+            detect(
+                "https://www.google.com/sorry/index?continue=x",
+                429,
+                "<html></html>"
+            ),
+            Some(Block::Captcha)
+        );
+        assert_eq!(
+            detect(
+                "https://ascii2d.net/search/uri",
+                403,
+                "<title>Just a moment...</title><script>window._cf_chl_opt={}</script>"
+            ),
+            Some(Block::Cloudflare)
+        );
+        assert_eq!(
+            detect("https://yandex.com/showcaptcha?retpath=x", 200, "..."),
+            Some(Block::SmartCaptcha)
+        );
+        assert_eq!(
+            detect(
+                "https://yandex.com/images/search",
+                200,
+                "<form class=\"CheckboxCaptcha\" id=\"checkbox-captcha-form\">"
+            ),
+            Some(Block::SmartCaptcha)
+        );
+        // 结果页加载 SmartCaptcha 脚本不算拦截
+        assert_eq!(
+            detect(
+                "https://yandex.com/images/search",
+                200,
+                "<script src=\"https://smartcaptcha.yandexcloud.net/captcha.js\"></script><div class=\"SmartCaptcha-Widget\"></div>"
+            ),
+            None
+        );
+        // 正常结果页中注入的 bot 管理脚本
+        assert_eq!(
+            detect(
+                "https://ascii2d.net/search/color/abc",
+                200,
+                "<div class=\"item-box\"></div><script>window.__CF$cv$params={r:'8f',t:'MTc='};\
+                 a.src='/cdn-cgi/challenge-platform/scripts/jsd/main.js';</script>"
+            ),
+            None
+        );
+        assert_eq!(detect("https://iqdb.org/", 200, "  \n"), Some(Block::Empty));
+        assert_eq!(
+            detect(
+                "https://iqdb.org/",
+                200,
+                "<table>No relevant matches</table>"
+            ),
+            None
+        );
+        assert!(matches!(
+            check(
+                "Google",
+                "https://www.google.com/sorry/index",
+                429,
+                &headers,
+                ""
+            ),
+            Err(Error::Captcha { engine: "Google" })
+        ));
+    }
+}
@@ -0,0 +1,476 @@
+use crate::canonical;
+use crate::engines::ImageSearch;
+use crate::error::Result;
+use crate::preprocess::UploadConstraints;
+use crate::types::{SearchOptions, SearchResult};
+use async_trait::async_trait;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// 默认缓存一天，搜索引擎的索引更新不算频繁
+const DEFAULT_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// 正在写入的临时文件的扩展名
+const TMP_EXTENSION: &str = "tmp";
+
+/// 缓存命中统计
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// 已过期而被视为未命中的次数，包含在 `misses` 中
+    pub expired: u64,
+    pub writes: u64,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    expired: AtomicU64,
+    writes: AtomicU64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheEntry {
+    /// 写入时间（UNIX 秒）
+    stored_at: u64,
+    page_url: String,
+    results: Vec<SearchResult>,
+}
+
+/// 磁盘缓存，按引擎分目录，每条搜索结果保存为一个 JSON 文件
+///
+/// 克隆后共享同一份统计，可以在多个引擎之间复用
+#[derive(Debug, Clone)]
+pub struct Cache {
+    dir: PathBuf,
+    ttl: Duration,
+    counters: Arc<Counters>,
+}
+
+impl Cache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            ttl: DEFAULT_TTL,
+            counters: Arc::default(),
+        }
+    }
+
+    /// 设置缓存有效期
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.counters.hits.load(Ordering::Relaxed),
+            misses: self.counters.misses.load(Ordering::Relaxed),
+            expired: self.counters.expired.load(Ordering::Relaxed),
+            writes: self.counters.writes.load(Ordering::Relaxed),
+        }
+    }
+
+    /// 删除某张图片在某个引擎下的所有缓存
+    pub async fn invalidate_bytes(&self, engine: &str, bytes: &[u8]) -> Result<()> {
+        self.invalidate(engine, &bytes_key(bytes)).await
+    }
+
+    /// 删除某个图片链接在某个引擎下的所有缓存
+    pub async fn invalidate_url(&self, engine: &str, url: &str) -> Result<()> {
+        self.invalidate(engine, &url_key(url)).await
+    }
+
+    /// 清空某个引擎的缓存
+    pub async fn clear_engine(&self, engine: &str) -> Result<()> {
+        remove_dir(&self.engine_dir(engine)).await
+    }
+
+    /// 清空全部缓存
+    pub async fn clear(&self) -> Result<()> {
+        remove_dir(&self.dir).await
+    }
+
+    /// 删除所有已过期的缓存文件，返回删除的数量
+    ///
+    /// 正在写入的临时文件与缓存目录中的其他文件不会被删除
+    pub async fn purge_expired(&self) -> Result<usize> {
+        let mut removed = 0;
+        let mut engines = match tokio::fs::read_dir(&self.dir).await {
+            Ok(engines) => engines,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e.into()),
+        };
+        while let Some(engine) = engines.next_entry().await? {
+            if !engine.file_type().await?.is_dir() {
+                continue;
+            }
+            let mut files = tokio::fs::read_dir(engine.path()).await?;
+            while let Some(file) = files.next_entry().await? {
+                let path = file.path();
+                let is_entry = path.extension().is_some_and(|ext| ext == "json");
+                if !is_entry || !file.file_type().await?.is_file() {
+                    continue;
+                }
+                let fresh = self.read(&path).await.is_some_and(|e| self.is_fresh(&e));
+                if !fresh {
+                    tokio::fs::remove_file(&path).await?;
+                    removed += 1;
+                }
+            }
+        }
+        Ok(removed)
+    }
+
+    async fn get(&self, path: &Path) -> Option<(String, Vec<SearchResult>)> {
+        let entry = match self.read(path).await {
+            Some(entry) if !self.is_fresh(&entry) => {
+                self.counters.expired.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+            entry => entry,
+        };
+        let counter = if entry.is_some() {
+            &self.counters.hits
+        } else {
+            &self.counters.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        entry.map(|entry| (entry.page_url, entry.results))
+    }
+
+    /// 读取缓存文件，文件不存在或损坏都视为未命中
+    async fn read(&self, path: &Path) -> Option<CacheEntry> {
+        let data = tokio::fs::read(path).await.ok()?;
+        let entry: CacheEntry = match serde_json::from_slice(&data) {
+            Ok(entry) => entry,
+            Err(e) => {
+                debug!("缓存文件损坏 {}: {}", path.display(), e);
+                return None;
+            }
+        };
+        Some(entry)
+    }
+
+    fn is_fresh(&self, entry: &CacheEntry) -> bool {
+        now().saturating_sub(entry.stored_at) < self.ttl.as_secs()
+    }
+
+    async fn put(&self, path: &Path, page_url: &str, results: &[SearchResult]) -> Result<()> {
+        let entry = CacheEntry {
+            stored_at: now(),
+            page_url: page_url.to_string(),
+            results: results.to_vec(),
+        };
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        // 先写临时文件再重命名，避免并发读取到写了一半的文件；
+        // 临时文件名各不相同，同时写同一条缓存的任务不会互相覆盖
+        static SEQ: AtomicU64 = AtomicU64::new(0);
+        let tmp = path.with_extension(format!(
+            "{}-{}.{}",
+            std::process::id(),
+            SEQ.fetch_add(1, Ordering::Relaxed),
+            TMP_EXTENSION
+        ));
+        tokio::fs::write(&tmp, serde_json::to_vec(&entry)?).await?;
+        tokio::fs::rename(&tmp, path).await?;
+        self.counters.writes.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    async fn invalidate(&self, engine: &str, key: &str) -> Result<()> {
+        let dir = self.engine_dir(engine);
+        let mut files = match tokio::fs::read_dir(&dir).await {
+            Ok(files) => files,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        // 同一张图片在不同搜索选项下会有多个文件，文件名均以内容键开头
+        while let Some(file) = files.next_entry().await? {
+            let name = file.file_name().to_string_lossy().into_owned();
+            if name.starts_with(key) && name.ends_with(".json") {
+                tokio::fs::remove_file(file.path()).await?;
+            }
+        }
+        Ok(())
+    }
+
+    fn engine_dir(&self, engine: &str) -> PathBuf {
+        let name: String = engine
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() {
+                    c.to_ascii_lowercase()
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        self.dir.join(name)
+    }
+
+    fn path(&self, engine: &str, config: &str, key: &str, options: &SearchOptions) -> PathBuf {
+        self.engine_dir(engine)
+            .join(format!("{}-{}.json", key, options_key(config, options)))
+    }
+}
+
+/// 为任意引擎加上磁盘缓存，相同图片在有效期内不再访问网络
+pub struct CachedSearch<S> {
+    engine: S,
+    cache: Cache,
+}
+
+impl<S: ImageSearch> CachedSearch<S> {
+    pub fn new(engine: S, cache: Cache) -> Self {
+        Self { engine, cache }
+    }
+
+    pub fn cache(&self) -> &Cache {
+        &self.cache
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.engine
+    }
+
+    async fn cached<F>(
+        &self,
+        key: String,
+        options: &SearchOptions,
+        search: F,
+    ) -> Result<(String, Vec<SearchResult>)>
+    where
+        F: std::future::Future<Output = Result<(String, Vec<SearchResult>)>> + Send,
+    {
+        let path = self
+            .cache
+            .path(self.engine.name(), &self.engine.cache_key(), &key, options);
+        if let Some(hit) = self.cache.get(&path).await {
+            debug!("{} 命中缓存 {}", self.engine.name(), path.display());
+            return Ok(hit);
+        }
+
+        let (page_url, results) = search.await?;
+        // 写缓存失败不影响本次搜索结果
+        if let Err(e) = self.cache.put(&path, &page_url, &results).await {
+            debug!("写入缓存失败 {}: {}", path.display(), e);
+        }
+        Ok((page_url, results))
+    }
+}
+
+#[async_trait]
+impl<S: ImageSearch> ImageSearch for CachedSearch<S> {
+    fn name(&self) -> &'static str {
+        self.engine.name()
+    }
+
+    fn upload_constraints(&self) -> UploadConstraints {
+        self.engine.upload_constraints()
+    }
+
+    fn cache_key(&self) -> String {
+        self.engine.cache_key()
+    }
+
+    async fn search_url(
+        &self,
+        url: &str,
+        options: &SearchOptions,
+    ) -> Result<(String, Vec<SearchResult>)> {
+        self.cached(url_key(url), options, self.engine.search_url(url, options))
+            .await
+    }
+
+    async fn search_bytes(
+        &self,
+        bytes: &[u8],
+        options: &SearchOptions,
+    ) -> Result<(String, Vec<SearchResult>)> {
+        self.cached(
+            bytes_key(bytes),
+            options,
+            self.engine.search_bytes(bytes, options),
+        )
+        .await
+    }
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+fn bytes_key(bytes: &[u8]) -> String {
+    sha256_hex(bytes)
+}
+
+/// 链接只统一协议、域名与跟踪参数；同一作品的不同图片（例如 pixiv 的 `_p0` 与 `_p1`）
+/// 搜索结果不同，不能按作品合并
+fn url_key(url: &str) -> String {
+    let url = canonical::strip_tracking(url).map_or_else(|| url.to_string(), String::from);
+    sha256_hex(format!("url:{}", url).as_bytes())
+}
+
+/// 影响结果内容的引擎配置与选项
+///
+/// 代理与超时不影响结果；裁边与校验在缓存的 `search_bytes` 之外进行，也不参与
+fn options_key(config: &str, options: &SearchOptions) -> String {
+    let fingerprint = format!(
+        "{}|{:?}|{}",
+        config, options.min_similarity, options.hide_explicit
+    );
+    sha256_hex(fingerprint.as_bytes())[..8].to_string()
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+async fn remove_dir(dir: &Path) -> Result<()> {
+    match tokio::fs::remove_dir_all(dir).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    struct Counting(AtomicUsize);
+
+    #[async_trait]
+    impl ImageSearch for Counting {
+        fn name(&self) -> &'static str {
+            "Counting Engine"
+        }
+
+        async fn search_url(
+            &self,
+            url: &str,
+            _options: &SearchOptions,
+        ) -> Result<(String, Vec<SearchResult>)> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok((url.to_string(), Vec::new()))
+        }
+
+        async fn search_bytes(
+            &self,
+            bytes: &[u8],
+            options: &SearchOptions,
+        ) -> Result<(String, Vec<SearchResult>)> {
+            self.search_url(&bytes.len().to_string(), options).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cache_hit_and_invalidate() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(dir.path());
+        let engine = CachedSearch::new(Counting(AtomicUsize::new(0)), cache.clone());
+        let options = SearchOptions::default();
+
+        engine.search_bytes(b"image", &options).await.unwrap();
+        let (page_url, _) = engine.search_bytes(b"image", &options).await.unwrap();
+        assert_eq!(page_url, "5");
+        assert_eq!(engine.inner().0.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                hits: 1,
+                misses: 1,
+                expired: 0,
+                writes: 1
+            }
+        );
+
+        cache
+            .invalidate_bytes(engine.name(), b"image")
+            .await
+            .unwrap();
+        engine.search_bytes(b"image", &options).await.unwrap();
+        assert_eq!(engine.inner().0.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_expired_entry_is_a_miss() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(dir.path()).with_ttl(Duration::ZERO);
+        let engine = CachedSearch::new(Counting(AtomicUsize::new(0)), cache.clone());
+        let options = SearchOptions::default();
+
+        engine
+            .search_url("https://a/1.png", &options)
+            .await
+            .unwrap();
+        engine
+            .search_url("https://a/1.png", &options)
+            .await
+            .unwrap();
+        assert_eq!(engine.inner().0.load(Ordering::SeqCst), 2);
+        assert_eq!(cache.stats().expired, 1);
+        assert_eq!(cache.purge_expired().await.unwrap(), 1);
+    }
+
+    #[test]
+    fn test_url_key_keeps_images_of_one_work_apart() {
+        let p0 = "https://i.pximg.net/img-original/img/2024/01/01/00/00/00/81234567_p0.png";
+        let p1 = "https://i.pximg.net/img-original/img/2024/01/01/00/00/00/81234567_p1.png";
+        assert_ne!(url_key(p0), url_key(p1));
+        assert_eq!(
+            url_key("http://www.example.com/a.png?utm_source=x#top"),
+            url_key("https://example.com/a.png")
+        );
+
+        // 裁边与校验不影响缓存的搜索结果
+        let options = SearchOptions {
+            auto_crop: true,
+            verify: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            options_key("", &options),
+            options_key("", &SearchOptions::default())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_engine_config_and_purge_skips_foreign_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(dir.path()).with_ttl(Duration::ZERO);
+        let options = SearchOptions::default();
+
+        // 同名但配置不同的引擎使用不同的缓存文件
+        assert_ne!(
+            cache.path("IQDB", "three_d: false", "key", &options),
+            cache.path("IQDB", "three_d: true", "key", &options)
+        );
+
+        // 正在写入的临时文件、其他文件和根目录下的普通文件都不会被清理
+        let engine_dir = cache.engine_dir("IQDB");
+        std::fs::create_dir_all(&engine_dir).unwrap();
+        std::fs::write(engine_dir.join("key-0000.123-0.tmp"), b"{").unwrap();
+        std::fs::write(engine_dir.join("notes.txt"), b"").unwrap();
+        std::fs::write(dir.path().join("README"), b"").unwrap();
+        assert_eq!(cache.purge_expired().await.unwrap(), 0);
+        assert!(engine_dir.join("key-0000.123-0.tmp").exists());
+    }
+}
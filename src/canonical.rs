@@ -0,0 +1,320 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use url::Url;
+
+/// 能够识别作品 id 的站点
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Site {
+    Pixiv,
+    Twitter,
+    Danbooru,
+    Gelbooru,
+    Yandere,
+    Konachan,
+    DeviantArt,
+    Nhentai,
+    EHentai,
+}
+
+impl fmt::Display for Site {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Site::Pixiv => write!(f, "Pixiv"),
+            Site::Twitter => write!(f, "Twitter"),
+            Site::Danbooru => write!(f, "Danbooru"),
+            Site::Gelbooru => write!(f, "Gelbooru"),
+            Site::Yandere => write!(f, "yande.re"),
+            Site::Konachan => write!(f, "Konachan"),
+            Site::DeviantArt => write!(f, "DeviantArt"),
+            Site::Nhentai => write!(f, "nhentai"),
+            Site::EHentai => write!(f, "E-Hentai"),
+        }
+    }
+}
+
+/// 从链接中识别出的站点与作品 id，同一作品的不同链接形式会得到相同的值
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct SourceRef {
+    pub site: Site,
+    /// 站点内的作品 id；e-hentai 为 `gid/token`
+    pub id: String,
+}
+
+impl SourceRef {
+    /// 识别链接，无法识别的站点返回 `None`
+    pub fn parse(url: &str) -> Option<Self> {
+        let url = parse_url(url)?;
+        let host = url.host_str()?.to_lowercase();
+        let host = host.strip_prefix("www.").unwrap_or(&host);
+        let segments: Vec<&str> = url
+            .path_segments()
+            .map(|s| s.filter(|s| !s.is_empty()).collect())
+            .unwrap_or_default();
+        let query = |name: &str| {
+            url.query_pairs()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.into_owned())
+        };
+
+        let (site, id) = match host {
+            "pixiv.net" => (Site::Pixiv, pixiv_id(&segments, query("illust_id"))?),
+            // 原图与缩略图文件名以作品 id 开头，例如 12345678_p0.png；头像等其他路径不是作品
+            "i.pximg.net" => (Site::Pixiv, pximg_id(&segments)?),
+            "twitter.com" | "mobile.twitter.com" | "x.com" | "fxtwitter.com" | "vxtwitter.com"
+            | "fixupx.com" => (Site::Twitter, after(&segments, "status")?),
+            "danbooru.donmai.us" => (Site::Danbooru, post_id(&segments)?),
+            "gelbooru.com" => (Site::Gelbooru, digits(&query("id")?)?),
+            "yande.re" => (Site::Yandere, post_id(&segments)?),
+            "konachan.com" | "konachan.net" => (Site::Konachan, post_id(&segments)?),
+            "nhentai.net" => (Site::Nhentai, digits(after(&segments, "g")?.as_str())?),
+            "e-hentai.org" | "exhentai.org" => (Site::EHentai, gallery_id(&segments)?),
+            host if host == "deviantart.com" || host.ends_with(".deviantart.com") => {
+                (Site::DeviantArt, deviation_id(&segments)?)
+            }
+            _ => return None,
+        };
+        Some(Self { site, id })
+    }
+
+    /// 该作品的标准链接
+    pub fn url(&self) -> String {
+        let id = &self.id;
+        match self.site {
+            Site::Pixiv => format!("https://www.pixiv.net/artworks/{}", id),
+            Site::Twitter => format!("https://x.com/i/status/{}", id),
+            Site::Danbooru => format!("https://danbooru.donmai.us/posts/{}", id),
+            Site::Gelbooru => format!("https://gelbooru.com/index.php?page=post&s=view&id={}", id),
+            Site::Yandere => format!("https://yande.re/post/show/{}", id),
+            Site::Konachan => format!("https://konachan.com/post/show/{}", id),
+            Site::DeviantArt => format!("https://www.deviantart.com/deviation/{}", id),
+            Site::Nhentai => format!("https://nhentai.net/g/{}/", id),
+            Site::EHentai => format!("https://e-hentai.org/g/{}/", id),
+        }
+    }
+}
+
+impl fmt::Display for SourceRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.site, self.id)
+    }
+}
+
+/// 规范化链接：已知站点转换为作品的标准链接，其他链接按 `normalize` 处理
+pub fn canonicalize(url: &str) -> Option<String> {
+    match SourceRef::parse(url) {
+        Some(source) => Some(source.url()),
+        None => normalize(url),
+    }
+}
+
+/// 只做写法上的规范化：在 `strip_tracking` 的基础上再去掉结尾斜杠，
+/// 不会把同一作品的不同图片合并为一个链接
+pub fn normalize(url: &str) -> Option<String> {
+    let mut parsed = strip_tracking(url)?;
+    let path = parsed.path().trim_end_matches('/').to_string();
+    parsed.set_path(&path);
+    Some(parsed.to_string())
+}
+
+/// 统一为 https，去掉 `www.`、片段与跟踪参数，路径与其余参数保持原样
+pub(crate) fn strip_tracking(url: &str) -> Option<Url> {
+    let mut parsed = parse_url(url)?;
+    if parsed.scheme() == "http" {
+        parsed.set_scheme("https").ok()?;
+    }
+    let host = parsed.host_str()?.to_string();
+    if let Some(bare) = host.strip_prefix("www.") {
+        parsed.set_host(Some(bare)).ok()?;
+    }
+    parsed.set_fragment(None);
+    let query: Vec<(String, String)> = parsed
+        .query_pairs()
+        .filter(|(key, _)| !is_tracking_param(key))
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect();
+    if query.is_empty() {
+        parsed.set_query(None);
+    } else {
+        parsed.query_pairs_mut().clear().extend_pairs(query);
+    }
+    Some(parsed)
+}
+
+pub(crate) fn parse_url(url: &str) -> Option<Url> {
+    let url = url.trim();
+    if url.starts_with("//") {
+        Url::parse(&format!("https:{}", url)).ok()
+    } else {
+        Url::parse(url).ok()
+    }
+}
+
+fn is_tracking_param(key: &str) -> bool {
+    key.starts_with("utm_") || matches!(key, "ref" | "ref_src" | "fbclid" | "gclid")
+}
+
+fn digits(s: &str) -> Option<String> {
+    (!s.is_empty() && s.bytes().all(|b| b.is_ascii_digit())).then(|| s.to_string())
+}
+
+fn leading_digits(s: &str) -> Option<String> {
+    let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    digits(&s[..end])
+}
+
+/// 路径中紧跟在 `name` 之后的数字段
+fn after(segments: &[&str], name: &str) -> Option<String> {
+    let pos = segments.iter().position(|s| *s == name)?;
+    digits(segments.get(pos + 1)?)
+}
+
+fn pixiv_id(segments: &[&str], illust_id: Option<String>) -> Option<String> {
+    // member_illust.php?mode=medium&illust_id=123
+    if let Some(id) = illust_id {
+        return digits(&id);
+    }
+    // /artworks/123、/en/artworks/123、/i/123
+    after(segments, "artworks").or_else(|| after(segments, "i"))
+}
+
+/// `/img-original/img/.../123_p0.png`，缩略图路径前面可能还有 `/c/250x250/`
+fn pximg_id(segments: &[&str]) -> Option<String> {
+    if !segments
+        .iter()
+        .any(|s| matches!(*s, "img-original" | "img-master" | "custom-thumb"))
+    {
+        return None;
+    }
+    leading_digits(segments.last()?)
+}
+
+/// `/posts/123` 或 `/post/show/123`
+fn post_id(segments: &[&str]) -> Option<String> {
+    after(segments, "posts").or_else(|| after(segments, "show"))
+}
+
+/// `/g/{gid}/{token}/`，只有 gid 时无法构造链接，不视为有效作品
+fn gallery_id(segments: &[&str]) -> Option<String> {
+    let pos = segments.iter().position(|s| *s == "g")?;
+    let gid = digits(segments.get(pos + 1)?)?;
+    let token = segments.get(pos + 2)?;
+    Some(format!("{}/{}", gid, token))
+}
+
+/// `/{user}/art/{slug}-{id}` 或标准链接使用的 `/deviation/{id}`
+fn deviation_id(segments: &[&str]) -> Option<String> {
+    if let Some(id) = after(segments, "deviation") {
+        return Some(id);
+    }
+    let pos = segments.iter().position(|s| *s == "art")?;
+    let slug = segments.get(pos + 1)?;
+    digits(slug.rsplit('-').next()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(url: &str) -> Option<(Site, String)> {
+        SourceRef::parse(url).map(|s| (s.site, s.id))
+    }
+
+    #[test]
+    fn test_source_ref() {
+        let cases = [
+            ("https://www.pixiv.net/en/artworks/123", Site::Pixiv, "123"),
+            (
+                "https://www.pixiv.net/member_illust.php?mode=medium&illust_id=123",
+                Site::Pixiv,
+                "123",
+            ),
+            (
+                "https://i.pximg.net/img-original/img/2020/01/01/00/00/00/123_p0.png",
+                Site::Pixiv,
+                "123",
+            ),
+            (
+                "https://twitter.com/user/status/456?s=20&t=abc",
+                Site::Twitter,
+                "456",
+            ),
+            (
+                "https://x.com/user/status/456/photo/1",
+                Site::Twitter,
+                "456",
+            ),
+            (
+                "https://danbooru.donmai.us/post/show/789",
+                Site::Danbooru,
+                "789",
+            ),
+            (
+                "https://gelbooru.com/index.php?page=post&s=view&id=789",
+                Site::Gelbooru,
+                "789",
+            ),
+            ("https://yande.re/post/show/1", Site::Yandere, "1"),
+            ("http://konachan.net/post/show/2/tags", Site::Konachan, "2"),
+            (
+                "https://www.deviantart.com/user/art/Some-Title-987",
+                Site::DeviantArt,
+                "987",
+            ),
+            ("https://nhentai.net/g/177013/", Site::Nhentai, "177013"),
+            (
+                "https://exhentai.org/g/123/abcdef/",
+                Site::EHentai,
+                "123/abcdef",
+            ),
+        ];
+        for (url, site, id) in cases {
+            assert_eq!(parse(url), Some((site, id.to_string())), "{}", url);
+        }
+        assert_eq!(parse("https://www.pixiv.net/users/1"), None);
+        assert_eq!(
+            parse("https://i.pximg.net/user-profile/img/2020/01/01/00/00/00/123_170.png"),
+            None
+        );
+        assert_eq!(parse("https://example.com/posts/1"), None);
+    }
+
+    #[test]
+    fn test_canonicalize() {
+        assert_eq!(
+            canonicalize("//twitter.com/user/status/456?s=20").as_deref(),
+            Some("https://x.com/i/status/456")
+        );
+        assert_eq!(
+            canonicalize("https://example.com/a?utm_source=x&page=2#top").as_deref(),
+            Some("https://example.com/a?page=2")
+        );
+        assert_eq!(
+            canonicalize("http://www.Example.com/a/?utm_source=x#top").as_deref(),
+            Some("https://example.com/a")
+        );
+        assert_eq!(canonicalize("not a url"), None);
+    }
+
+    #[test]
+    fn test_standard_url_round_trips() {
+        let sources = [
+            (Site::Pixiv, "123"),
+            (Site::Twitter, "456"),
+            (Site::Danbooru, "789"),
+            (Site::Gelbooru, "789"),
+            (Site::Yandere, "1"),
+            (Site::Konachan, "2"),
+            (Site::DeviantArt, "987"),
+            (Site::Nhentai, "177013"),
+            (Site::EHentai, "123/abcdef"),
+        ];
+        for (site, id) in sources {
+            let source = SourceRef {
+                site,
+                id: id.to_string(),
+            };
+            assert_eq!(SourceRef::parse(&source.url()), Some(source.clone()));
+            assert_eq!(canonicalize(&source.url()), Some(source.url()));
+        }
+    }
+}
@@ -0,0 +1,456 @@
+use crate::error::{Error, Result};
+use crate::network::Form;
+use base64::{engine::general_purpose, Engine as _};
+use log::debug;
+use reqwest::header::SET_COOKIE;
+use reqwest::{Method, Response, ResponseBuilderExt};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use url::Url;
+
+/// 录制时不保存的响应头，正文已由 reqwest 解压，保留它们会让回放的响应无法读取
+const SKIPPED_HEADERS: &[&str] = &["content-encoding", "content-length", "transfer-encoding"];
+
+/// 录制时值被替换为 `REDACTED` 的查询参数与表单字段，避免把凭据写进磁带文件；
+/// 响应中 `Set-Cookie` 的值同样会被替换
+const SECRET_PARAMS: &[&str] = &["api_key", "apikey", "key", "token", "access_token"];
+const REDACTED: &str = "REDACTED";
+
+/// 默认不参与匹配的查询参数：Google Lens 跟随重定向时加入的提交时间戳
+const VOLATILE_PARAMS: &[&str] = &["qsubts"];
+
+/// 磁带的工作模式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// 正常发送请求，并把请求与响应追加到磁带文件
+    Record,
+    /// 不访问网络，从磁带中查找匹配的响应
+    Replay,
+}
+
+/// 用于匹配的请求信息
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub url: String,
+    /// multipart 表单中的文本字段
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub form: Vec<(String, String)>,
+    /// multipart 表单中的文件字段名与文件名，不保存文件内容
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<(String, String)>,
+}
+
+impl RecordedRequest {
+    /// 记录请求信息，查询参数与表单中的凭据会被替换为 `REDACTED`
+    pub fn new(method: &Method, url: &Url, form: Option<&Form>) -> Self {
+        let mut url = url.clone();
+        if url.query().is_some() {
+            let pairs: Vec<(String, String)> = url
+                .query_pairs()
+                .map(|(name, value)| redact(&name, &value))
+                .collect();
+            url.query_pairs_mut().clear().extend_pairs(pairs);
+        }
+        let (form, files) = match form {
+            Some(form) => (
+                form.texts()
+                    .iter()
+                    .map(|(name, value)| redact(name, value))
+                    .collect(),
+                form.parts()
+                    .iter()
+                    .map(|(name, part)| (name.clone(), part.file_name.clone()))
+                    .collect(),
+            ),
+            None => Default::default(),
+        };
+        Self {
+            method: method.to_string(),
+            url: url.to_string(),
+            form,
+            files,
+        }
+    }
+
+    /// 方法、域名、路径、查询参数与表单字段都相同即视为同一请求
+    ///
+    /// `ignored` 中的查询参数不参与匹配，用于每次请求都会变化的时间戳等参数
+    pub fn matches(&self, other: &RecordedRequest, ignored: &[String]) -> bool {
+        let key = |url: &str| {
+            let url = Url::parse(url).ok()?;
+            let mut query: Vec<(String, String)> = url
+                .query_pairs()
+                .filter(|(name, _)| !ignored.iter().any(|ignored| ignored == name))
+                .map(|(name, value)| redact(&name, &value))
+                .collect();
+            query.sort();
+            Some((
+                url.host_str().map(str::to_string),
+                url.path().to_string(),
+                query,
+            ))
+        };
+        self.method == other.method
+            && key(&self.url) == key(&other.url)
+            && self.form == other.form
+            && self.files == other.files
+    }
+}
+
+fn redact(name: &str, value: &str) -> (String, String) {
+    let value = if SECRET_PARAMS.contains(&name.to_ascii_lowercase().as_str()) {
+        REDACTED
+    } else {
+        value
+    };
+    (name.to_string(), value.to_string())
+}
+
+/// 录制的响应，经过重定向时 `url` 为最终地址
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedResponse {
+    pub status: u16,
+    pub url: String,
+    #[serde(default)]
+    pub headers: Vec<(String, String)>,
+    #[serde(default)]
+    pub body: String,
+    /// 非 UTF-8 的正文（例如图片）以 base64 保存
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body_base64: Option<String>,
+}
+
+impl RecordedResponse {
+    fn body_bytes(&self) -> Result<Vec<u8>> {
+        match &self.body_base64 {
+            Some(encoded) => Ok(general_purpose::STANDARD.decode(encoded)?),
+            None => Ok(self.body.clone().into_bytes()),
+        }
+    }
+
+    fn to_response(&self) -> Result<Response> {
+        let mut builder = http::Response::builder()
+            .status(self.status)
+            .url(Url::parse(&self.url)?);
+        for (name, value) in &self.headers {
+            builder = builder.header(name, value);
+        }
+        let response = builder
+            .body(self.body_bytes()?)
+            .map_err(|e| Error::InvalidResponse(format!("磁带中的响应无效: {}", e)))?;
+        Ok(Response::from(response))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Interaction {
+    pub request: RecordedRequest,
+    pub response: RecordedResponse,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Tape {
+    interactions: Vec<Interaction>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    interactions: Vec<Interaction>,
+    // 回放时已经使用过的记录
+    used: Vec<bool>,
+}
+
+/// 保存请求与响应的磁带文件，用于离线测试完整的请求流程
+///
+/// 通过 `set_cassette` 挂到某个引擎上后，该引擎经过网络层的所有请求都会被录制或回放
+#[derive(Debug)]
+pub struct Cassette {
+    path: PathBuf,
+    mode: Mode,
+    ignored_params: Vec<String>,
+    state: Mutex<State>,
+}
+
+impl Cassette {
+    /// 录制到指定文件，文件已存在时会被覆盖
+    pub fn record(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            mode: Mode::Record,
+            ignored_params: default_ignored_params(),
+            state: Mutex::default(),
+        }
+    }
+
+    /// 读取磁带文件用于回放
+    pub fn replay(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let tape: Tape = serde_json::from_str(&std::fs::read_to_string(&path)?)?;
+        Ok(Self::from_interactions(path, tape.interactions))
+    }
+
+    /// 使用内存中的记录回放
+    pub fn from_interactions(path: impl Into<PathBuf>, interactions: Vec<Interaction>) -> Self {
+        let used = vec![false; interactions.len()];
+        Self {
+            path: path.into(),
+            mode: Mode::Replay,
+            ignored_params: default_ignored_params(),
+            state: Mutex::new(State { interactions, used }),
+        }
+    }
+
+    /// 匹配时忽略某个查询参数，默认只忽略 Google Lens 的 `qsubts`
+    pub fn with_ignored_param(mut self, name: impl Into<String>) -> Self {
+        self.ignored_params.push(name.into());
+        self
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// 回放时尚未使用的记录数，可用于检查请求流程是否完整
+    pub fn remaining(&self) -> usize {
+        let state = self.lock();
+        state.used.iter().filter(|used| !**used).count()
+    }
+
+    /// 按顺序查找第一条未使用且匹配的记录
+    ///
+    /// 同一请求出现多次时（例如翻页），依次返回录制时的各个响应
+    pub fn find(&self, request: &RecordedRequest) -> Result<Response> {
+        let mut state = self.lock();
+        let State { interactions, used } = &mut *state;
+        let index = interactions
+            .iter()
+            .zip(used.iter())
+            .position(|(interaction, used)| {
+                !used && interaction.request.matches(request, &self.ignored_params)
+            })
+            .ok_or_else(|| {
+                Error::InvalidResponse(format!(
+                    "磁带 {} 中没有匹配的请求: {} {}",
+                    self.path.display(),
+                    request.method,
+                    request.url
+                ))
+            })?;
+        used[index] = true;
+        debug!("回放 {} {}", request.method, request.url);
+        interactions[index].response.to_response()
+    }
+
+    /// 读取响应并追加到磁带文件，返回内容相同的新响应
+    pub async fn save(&self, request: RecordedRequest, mut response: Response) -> Result<Response> {
+        let _permit = crate::network::hold_permit(&mut response);
+        let status = response.status().as_u16();
+        let url = response.url().to_string();
+        let headers = response
+            .headers()
+            .iter()
+            .filter(|(name, _)| !SKIPPED_HEADERS.contains(&name.as_str()))
+            .filter_map(|(name, value)| {
+                let value = value.to_str().ok()?;
+                let value = if name == SET_COOKIE {
+                    redact_cookie(value)
+                } else {
+                    value.to_string()
+                };
+                Some((name.to_string(), value))
+            })
+            .collect();
+        let bytes = response.bytes().await?;
+        let (body, body_base64) = match std::str::from_utf8(&bytes) {
+            Ok(text) => (text.to_string(), None),
+            Err(_) => (
+                String::new(),
+                Some(general_purpose::STANDARD.encode(&bytes)),
+            ),
+        };
+        let recorded = RecordedResponse {
+            status,
+            url,
+            headers,
+            body,
+            body_base64,
+        };
+        let response = recorded.to_response()?;
+
+        let mut state = self.lock();
+        state.interactions.push(Interaction {
+            request,
+            response: recorded,
+        });
+        state.used.push(true);
+        // 每次请求后都写入文件，搜索中途出错时也能保留已录制的部分
+        let tape = Tape {
+            interactions: state.interactions.clone(),
+        };
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&self.path, serde_json::to_string_pretty(&tape)?)?;
+        Ok(response)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// 只替换 `Set-Cookie` 的值，保留名称与属性，回放时引擎仍能看到下发的 cookie
+fn redact_cookie(cookie: &str) -> String {
+    let (pair, attributes) = cookie.split_once(';').unwrap_or((cookie, ""));
+    let name = pair.split_once('=').map_or(pair, |(name, _)| name);
+    if attributes.is_empty() {
+        format!("{}={}", name, REDACTED)
+    } else {
+        format!("{}={};{}", name, REDACTED, attributes)
+    }
+}
+
+fn default_ignored_params() -> Vec<String> {
+    VOLATILE_PARAMS
+        .iter()
+        .map(|name| name.to_string())
+        .collect()
+}
+
+tokio::task_local! {
+    static SCOPED: HashMap<String, Arc<Cassette>>;
+}
+
+fn registry() -> &'static Mutex<HashMap<String, Arc<Cassette>>> {
+    static REGISTRY: OnceLock<Mutex<HashMap<String, Arc<Cassette>>>> = OnceLock::new();
+    REGISTRY.get_or_init(Default::default)
+}
+
+/// 获取某个引擎当前使用的磁带，`scope` 挂上的优先于 `set_cassette`
+pub fn cassette(engine: &str) -> Option<Arc<Cassette>> {
+    if let Ok(Some(cassette)) = SCOPED.try_with(|scoped| scoped.get(engine).cloned()) {
+        return Some(cassette);
+    }
+    let registry = registry().lock().unwrap_or_else(|e| e.into_inner());
+    registry.get(engine).cloned()
+}
+
+/// 只在 `future` 内为某个引擎挂上磁带
+///
+/// 磁带绑定在当前任务上，同时运行的其他任务（例如并行的测试）使用同名引擎也不受影响；
+/// `future` 内用 `tokio::spawn` 启动的新任务不会继承
+pub async fn scope<F: Future>(engine: &str, cassette: Arc<Cassette>, future: F) -> F::Output {
+    let mut scoped = SCOPED.try_with(Clone::clone).unwrap_or_default();
+    scoped.insert(engine.to_string(), cassette);
+    SCOPED.scope(scoped, future).await
+}
+
+/// 为某个引擎挂上磁带，对整个进程之后发出的请求生效，返回的句柄可用于检查回放进度
+///
+/// 适合单独运行的录制脚本；测试中请使用 `scope`，避免影响并行运行的其他测试
+pub fn set_cassette(engine: &str, cassette: Cassette) -> Arc<Cassette> {
+    let cassette = Arc::new(cassette);
+    let mut registry = registry().lock().unwrap_or_else(|e| e.into_inner());
+    registry.insert(engine.to_string(), Arc::clone(&cassette));
+    cassette
+}
+
+/// 取下某个引擎的磁带，恢复正常的网络请求
+pub fn clear_cassette(engine: &str) {
+    let mut registry = registry().lock().unwrap_or_else(|e| e.into_inner());
+    registry.remove(engine);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(method: &str, url: &str) -> RecordedRequest {
+        RecordedRequest {
+            method: method.to_string(),
+            url: url.to_string(),
+            form: Vec::new(),
+            files: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replay_in_order() {
+        let response = |body: &str| RecordedResponse {
+            status: 200,
+            url: "https://example.com/page".to_string(),
+            headers: vec![("content-type".to_string(), "text/html".to_string())],
+            body: body.to_string(),
+            body_base64: None,
+        };
+        let interactions = vec![
+            Interaction {
+                request: request("GET", "https://example.com/page?p=1&qsubts=1"),
+                response: response("first"),
+            },
+            Interaction {
+                request: request("GET", "https://example.com/page?p=1&qsubts=2"),
+                response: response("second"),
+            },
+        ];
+        let cassette = Cassette::from_interactions("memory.json", interactions);
+
+        // 不同的查询参数不匹配
+        assert!(cassette
+            .find(&request("GET", "https://example.com/page?p=2"))
+            .is_err());
+
+        // 时间戳不参与匹配，相同的请求依次返回录制的响应
+        let first = cassette
+            .find(&request("GET", "https://example.com/page?qsubts=9&p=1"))
+            .unwrap();
+        assert_eq!(first.url().as_str(), "https://example.com/page");
+        assert_eq!(first.text().await.unwrap(), "first");
+        let second = cassette
+            .find(&request("GET", "https://example.com/page?p=1"))
+            .unwrap();
+        assert_eq!(second.text().await.unwrap(), "second");
+        assert_eq!(cassette.remaining(), 0);
+
+        assert!(cassette
+            .find(&request("GET", "https://example.com/page?p=1"))
+            .is_err());
+        assert!(cassette
+            .find(&request("POST", "https://example.com/other"))
+            .is_err());
+    }
+
+    #[test]
+    fn test_secrets_are_redacted() {
+        let url = Url::parse("https://saucenao.com/search.php?api_key=secret&db=999").unwrap();
+        let form = Form::new().text("token", "secret").text("factor", "1.2");
+        let recorded = RecordedRequest::new(&Method::GET, &url, Some(&form));
+        assert_eq!(
+            recorded.url,
+            "https://saucenao.com/search.php?api_key=REDACTED&db=999"
+        );
+        assert_eq!(
+            recorded.form[0],
+            ("token".to_string(), "REDACTED".to_string())
+        );
+
+        // 使用其他 key 的请求仍能匹配到录制的结果
+        let url = Url::parse("https://saucenao.com/search.php?db=999&api_key=other").unwrap();
+        let live = RecordedRequest::new(&Method::GET, &url, Some(&form));
+        assert!(recorded.matches(&live, &[]));
+
+        assert_eq!(
+            redact_cookie("yandexuid=123456; Path=/; Domain=.yandex.com"),
+            "yandexuid=REDACTED; Path=/; Domain=.yandex.com"
+        );
+        assert_eq!(redact_cookie("session=abc"), "session=REDACTED");
+    }
+}
@@ -1,15 +1,24 @@
 use super::ImageSearch;
 use crate::error::{Error, Result};
+use crate::network::{Form, Network};
+use crate::preprocess::{self, UploadConstraints};
 use crate::types::{AdditionalInfo, SearchOptions, SearchResult};
 use crate::utils::normalize_url;
 use async_trait::async_trait;
-use reqwest::multipart;
+use image::ImageFormat;
 use scraper::{Html, Selector};
 
 const API_URL: &str = "https://ascii2d.net";
+const ENGINE: &str = "ASCII2D";
 
 pub struct Ascii2d {}
 
+impl Default for Ascii2d {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Ascii2d {
     pub fn new() -> Self {
         Self {}
@@ -86,55 +95,36 @@ impl Ascii2d {
             .build()?)
     }
 
-    async fn search_with_client(
-        &self,
-        client: &reqwest::Client,
-        url: &str,
-    ) -> Result<Vec<SearchResult>> {
+    /// 每次搜索使用新的 cookie 会话，限速在所有会话之间共享
+    fn build_network() -> Result<Network> {
+        Ok(Network::with_client(Self::build_client()?).for_engine(ENGINE))
+    }
+
+    async fn search_with_client(&self, network: &Network, url: &str) -> Result<Vec<SearchResult>> {
         // 首先访问主页获取 cookie
-        client.get(API_URL).send().await?;
+        network.get(API_URL).await?;
 
         // 然后进行色彩搜索
-        let form = multipart::Form::new().text("uri", url.to_string());
-        let response = client
-            .post(&format!("{}/search/uri", API_URL))
-            .multipart(form)
-            .send()
+        let form = Form::new().text("uri", url.to_string());
+        let response = network
+            .post_multipart(&format!("{}/search/uri", API_URL), &form)
             .await?;
 
         if !response.status().is_success() {
-            return Err(Error::Engine(format!(
-                "ASCII2D returned status code: {} - {}",
-                response.status(),
-                response.text().await?
-            )));
+            return Err(Error::http_status(ENGINE, response).await);
         }
 
-        let html = response.text().await?;
-        let mut results = self.parse_results(&html).await?;
+        let html = network.read_text(response).await?;
+        let mut results = Self::parse_results(&html)?;
 
         // 然后进行特征搜索
-        let bovw_url = format!(
-            "{}/search/bovw/{}",
-            API_URL,
-            html.split("/bovw/")
-                .nth(1)
-                .unwrap_or_default()
-                .split('"')
-                .next()
-                .unwrap_or_default()
-        );
-
-        let response = client.get(&bovw_url).send().await?;
-        if response.status().is_success() {
-            let html = response.text().await?;
-            results.extend(self.parse_results(&html).await?);
-        }
+        Self::extend_with_bovw(network, &html, &mut results).await?;
 
         Ok(results)
     }
 
-    async fn parse_results(&self, html: &str) -> Result<Vec<SearchResult>> {
+    /// 解析色彩搜索或特征搜索的结果页
+    pub fn parse_results(html: &str) -> Result<Vec<SearchResult>> {
         let document = Html::parse_document(html);
         let item_selector = Selector::parse(".item-box").unwrap();
         let link_selector = Selector::parse("a").unwrap();
@@ -158,35 +148,27 @@ impl Ascii2d {
                     .map(|h| h.text().collect::<String>())
                     .unwrap_or_default();
 
-                // 第一个链接通常是作者链接
-                let (author, author_url) = if let Some(author_link) = links.next() {
-                    (
-                        Some(author_link.text().collect::<String>()),
+                // 第一个链接是作品，第二个链接是作者
+                let Some(work_link) = links.next() else {
+                    continue;
+                };
+                let url = normalize_url(work_link.value().attr("href").unwrap_or_default())?;
+                let title = work_link.text().collect::<String>().trim().to_string();
+
+                let (author, author_url) = match links.next() {
+                    Some(author_link) => (
+                        Some(author_link.text().collect::<String>().trim().to_string()),
                         Some(normalize_url(
                             author_link.value().attr("href").unwrap_or_default(),
                         )?),
-                    )
-                } else {
-                    (None, None)
-                };
-
-                // 第二个链接通常是图片链接
-                let url = if let Some(source_link) = links.next() {
-                    normalize_url(source_link.value().attr("href").unwrap_or_default())?
-                } else {
-                    continue;
+                    ),
+                    None => (None, None),
                 };
 
-                let title = links
-                    .next()
-                    .map(|l| l.text().collect::<String>())
-                    .unwrap_or_default();
-
                 results.push(SearchResult {
                     title: Some(title),
                     url,
                     thumbnail,
-                    similarity: None,
                     source: "ASCII2D".to_string(),
                     index: Some(hash),
                     additional_info: Some(AdditionalInfo {
@@ -197,18 +179,55 @@ impl Ascii2d {
                         ext_urls: vec![],
                         ..Default::default()
                     }),
+                    ..Default::default()
                 });
             }
         }
 
         Ok(results)
     }
+
+    /// 色彩搜索结果页中指向特征搜索的链接
+    pub fn bovw_url(html: &str) -> Option<String> {
+        let hash = html.split("/bovw/").nth(1)?.split('"').next()?;
+        (!hash.is_empty()).then(|| format!("{}/search/bovw/{}", API_URL, hash))
+    }
+
+    /// 色彩搜索结果之后追加特征搜索的结果
+    async fn extend_with_bovw(
+        network: &Network,
+        html: &str,
+        results: &mut Vec<SearchResult>,
+    ) -> Result<()> {
+        let Some(bovw_url) = Self::bovw_url(html) else {
+            return Ok(());
+        };
+        let response = network.get(&bovw_url).await?;
+        if response.status().is_success() {
+            let html = network.read_text(response).await?;
+            results.extend(Self::parse_results(&html)?);
+        }
+        Ok(())
+    }
 }
 
 #[async_trait]
 impl ImageSearch for Ascii2d {
     fn name(&self) -> &'static str {
-        "ASCII2D"
+        ENGINE
+    }
+
+    fn upload_constraints(&self) -> UploadConstraints {
+        UploadConstraints {
+            formats: &[
+                ImageFormat::Jpeg,
+                ImageFormat::Png,
+                ImageFormat::Gif,
+                ImageFormat::WebP,
+            ],
+            max_dimension: None,
+            max_bytes: Some(5 * 1024 * 1024),
+        }
     }
 
     async fn search_url(
@@ -216,8 +235,8 @@ impl ImageSearch for Ascii2d {
         url: &str,
         _options: &SearchOptions,
     ) -> Result<(String, Vec<SearchResult>)> {
-        let client = Self::build_client()?;
-        let results = self.search_with_client(&client, url).await?;
+        let network = Self::build_network()?;
+        let results = self.search_with_client(&network, url).await?;
         Ok(("".to_string(), results))
     }
 
@@ -226,51 +245,29 @@ impl ImageSearch for Ascii2d {
         bytes: &[u8],
         _options: &SearchOptions,
     ) -> Result<(String, Vec<SearchResult>)> {
-        let client = Self::build_client()?;
+        let network = Self::build_network()?;
 
         // 首先访问主页获取 cookie
-        client.get(API_URL).send().await?;
+        network.get(API_URL).await?;
 
         // 然后进行色彩搜索
-        let part = multipart::Part::bytes(bytes.to_vec())
-            .file_name("image.png")
-            .mime_str("image/png")?;
-        let form = multipart::Form::new().part("file", part);
-
-        let response = client
-            .post(&format!("{}/search/file", API_URL))
-            .multipart(form)
-            .send()
+        let image = preprocess::prepare(bytes, &self.upload_constraints())
+            .map_err(|e| e.with_engine(self.name()))?;
+        let form = Form::new().part("file", image.into_part());
+
+        let response = network
+            .post_multipart(&format!("{}/search/file", API_URL), &form)
             .await?;
 
         if !response.status().is_success() {
-            return Err(Error::Engine(format!(
-                "ASCII2D returned status code: {} - {}",
-                response.status(),
-                response.text().await?
-            )));
+            return Err(Error::http_status(ENGINE, response).await);
         }
 
-        let html = response.text().await?;
-        let mut results = self.parse_results(&html).await?;
+        let html = network.read_text(response).await?;
+        let mut results = Self::parse_results(&html)?;
 
         // 然后进行特征搜索
-        let bovw_url = format!(
-            "{}/search/bovw/{}",
-            API_URL,
-            html.split("/bovw/")
-                .nth(1)
-                .unwrap_or_default()
-                .split('"')
-                .next()
-                .unwrap_or_default()
-        );
-
-        let response = client.get(&bovw_url).send().await?;
-        if response.status().is_success() {
-            let html = response.text().await?;
-            results.extend(self.parse_results(&html).await?);
-        }
+        Self::extend_with_bovw(&network, &html, &mut results).await?;
 
         Ok(("".to_string(), results))
     }
@@ -1,26 +1,97 @@
 use super::ImageSearch;
+use crate::canonical;
 use crate::error::{Error, Result};
+use crate::network::{Form, Network};
+use crate::preprocess::{self, UploadConstraints};
 use crate::types::{AdditionalInfo, SearchOptions, SearchResult};
 use async_trait::async_trait;
+use futures::stream::{self, Stream, TryStreamExt};
+use image::ImageFormat;
+use log::debug;
 use regex;
-use reqwest::multipart;
-use scraper::{Html, Selector};
+use scraper::{ElementRef, Html, Selector};
+use std::collections::HashSet;
+use std::sync::OnceLock;
+use url::Url;
 
 const API_URL: &str = "https://www.google.com";
+const ENGINE: &str = "Google";
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct GoogleResponse {
     pub results: Vec<SearchResult>,
     pub pages: Vec<String>,
     pub current_page: usize,
     pub url: String,
+    /// Google 对图片内容的猜测，例如 "Best guess for this image: hatsune miku"
+    pub best_guess: Option<String>,
+    /// "Pages that include matching images" 区块中的结果，带有匹配图片的尺寸
+    pub matching_pages: Vec<SearchResult>,
+    /// "Visually similar images" 区块中的图片
+    pub similar_images: Vec<SearchResult>,
+    /// 查看更多相似图片的链接
+    pub similar_url: Option<String>,
 }
 
-pub struct Google {}
+/// 分页抓取的终止条件，两个上限都为 `None` 时会一直翻到最后一页
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PageLimit {
+    /// 最多返回的页数
+    pub max_pages: Option<usize>,
+    /// 最多返回的结果总数，最后一页会被截断
+    pub max_results: Option<usize>,
+}
+
+impl PageLimit {
+    pub fn pages(max_pages: usize) -> Self {
+        Self {
+            max_pages: Some(max_pages),
+            max_results: None,
+        }
+    }
+
+    pub fn results(max_results: usize) -> Self {
+        Self {
+            max_pages: None,
+            max_results: Some(max_results),
+        }
+    }
+}
+
+enum PageState {
+    First(GoogleResponse),
+    Next {
+        last: GoogleResponse,
+        pages: usize,
+        results: usize,
+    },
+}
+
+pub struct Google {
+    // 所有请求共享同一个 client，翻页时沿用首次搜索得到的 cookie；
+    // 第一次请求时才创建，创建失败时由该请求返回错误
+    network: OnceLock<Network>,
+}
+
+impl Default for Google {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl Google {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            network: OnceLock::new(),
+        }
+    }
+
+    fn network(&self) -> Result<&Network> {
+        if let Some(network) = self.network.get() {
+            return Ok(network);
+        }
+        let network = Network::with_client(Self::build_client()?).for_engine(ENGINE);
+        Ok(self.network.get_or_init(|| network))
     }
 
     fn build_client() -> Result<reqwest::Client> {
@@ -47,52 +118,45 @@ impl Google {
             .build()?)
     }
 
-    async fn search_with_client(
-        &self,
-        client: &reqwest::Client,
-        url: &str,
-    ) -> Result<GoogleResponse> {
+    /// 通过图片 URL 搜索，返回包含分页信息的第一页结果
+    pub async fn search_url_page(&self, url: &str) -> Result<GoogleResponse> {
+        let network = self.network()?;
+        let client = network.client();
         // 构建搜索请求
         let search_url = format!("{}/searchbyimage?&image_url={}&client=Chrome", API_URL, url);
         println!("搜索 URL: {}", search_url);
 
-        let response = client
-            .get(&search_url)
-            .header(reqwest::header::REFERER, API_URL)
-            .send()
+        let response = network
+            .send(
+                client
+                    .get(&search_url)
+                    .header(reqwest::header::REFERER, API_URL),
+            )
             .await?;
 
         if !response.status().is_success() {
-            return Err(Error::Engine(format!(
-                "Google returned status code: {}",
-                response.status()
-            )));
+            return Err(Error::http_status(ENGINE, response).await);
         }
 
-        let html = response.text().await?;
-
-        // 检查是否被重定向到验证页面
-        if html.contains("Our systems have detected unusual traffic") {
-            return Err(Error::Engine("Google 要求验证，请稍后再试".to_string()));
-        }
+        let html = network.read_text(response).await?;
 
         // 尝试解析结果
-        let mut response = self.parse_response(&html, &search_url, 1).await?;
+        let mut response = Self::parse_response(&html, &search_url, 1)?;
 
         // 如果没有找到缩略图，尝试再次请求
         if response.results.is_empty() || response.results.iter().all(|r| r.thumbnail.is_none()) {
-            let response_retry = client.get(&search_url).send().await?;
+            let response_retry = network.get(&search_url).await?;
             if response_retry.status().is_success() {
-                response = self
-                    .parse_response(&response_retry.text().await?, &search_url, 1)
-                    .await?;
+                let html = network.read_text(response_retry).await?;
+                response = Self::parse_response(&html, &search_url, 1)?;
             }
         }
 
         Ok(response)
     }
 
-    async fn parse_response(&self, html: &str, url: &str, page: usize) -> Result<GoogleResponse> {
+    /// 解析结果页，`url` 与 `page` 为该页的链接与页码
+    pub fn parse_response(html: &str, url: &str, page: usize) -> Result<GoogleResponse> {
         let document = Html::parse_document(html);
 
         // 匹配分页链接
@@ -128,7 +192,19 @@ impl Google {
             }
         }
 
+        // 按文档顺序记录每个节点的位置，用来判断结果位于哪个区块之下
+        let positions: std::collections::HashMap<_, _> = document
+            .root_element()
+            .descendants()
+            .enumerate()
+            .map(|(pos, node)| (node.id(), pos))
+            .collect();
+        let matching_header = Self::find_heading(&document, "Pages that include matching images");
+        let similar_header = Self::find_heading(&document, "Visually similar images");
+        let matching_pos = matching_header.and_then(|el| positions.get(&el.id()).copied());
+
         let mut results = Vec::new();
+        let mut matching_pages = Vec::new();
         for (index, item) in document.select(&results_selector).enumerate() {
             let title = item
                 .select(&title_selector)
@@ -150,46 +226,177 @@ impl Google {
                 .and_then(|id| thumbnail_dict.get(id))
                 .cloned();
 
+            let size = Self::parse_size(&item.text().collect::<String>());
+
             if !url.is_empty() {
-                results.push(SearchResult {
+                let result = SearchResult {
                     title,
                     url,
                     thumbnail,
-                    similarity: None,
                     source: "Google".to_string(),
                     index: Some(index.to_string()),
-                    additional_info: Some(AdditionalInfo::default()),
-                });
+                    additional_info: Some(AdditionalInfo {
+                        size,
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                };
+
+                let in_matching_section = matching_pos
+                    .zip(positions.get(&item.id()))
+                    .is_some_and(|(header, pos)| *pos > header);
+                if in_matching_section {
+                    matching_pages.push(result.clone());
+                }
+                results.push(result);
             }
         }
 
-        println!("找到 {} 个结果", results.len());
+        let (similar_images, similar_url) = match similar_header {
+            Some(header) => Self::parse_similar_images(header, &thumbnail_dict),
+            None => (Vec::new(), None),
+        };
+
+        debug!("找到 {} 个结果", results.len());
         Ok(GoogleResponse {
             results,
             pages,
             current_page: page,
             url: url.to_string(),
+            best_guess: Self::parse_best_guess(&document),
+            matching_pages,
+            similar_images,
+            similar_url,
         })
     }
 
+    fn find_heading<'a>(document: &'a Html, text: &str) -> Option<ElementRef<'a>> {
+        let heading_selector = Selector::parse("h2, h3, div[role='heading'], span").unwrap();
+        document
+            .select(&heading_selector)
+            .find(|el| el.text().collect::<String>().trim() == text)
+    }
+
+    fn parse_best_guess(document: &Html) -> Option<String> {
+        // 新旧页面的 class 不同，优先使用文字定位
+        let container_selector = Selector::parse("div").unwrap();
+        let link_selector = Selector::parse("a").unwrap();
+        let guess = document
+            .select(&container_selector)
+            // 取最内层的区块，避免拿到外层容器里的第一个链接
+            .rfind(|el| {
+                let text = el.text().collect::<String>();
+                text.contains("Best guess for this image")
+                    || text.contains("Possible related search")
+            })
+            .and_then(|el| el.select(&link_selector).next())
+            .map(|a| a.text().collect::<String>());
+
+        let guess = guess.or_else(|| {
+            let fallback = Selector::parse("a.fKDtNb").unwrap();
+            document
+                .select(&fallback)
+                .next()
+                .map(|a| a.text().collect::<String>())
+        });
+
+        guess
+            .map(|text| text.trim().to_string())
+            .filter(|text| !text.is_empty())
+    }
+
+    fn parse_size(text: &str) -> Option<(u32, u32)> {
+        let size_regex = regex::Regex::new(r"(\d+)\s*[×x]\s*(\d+)").unwrap();
+        let caps = size_regex.captures(text)?;
+        Some((caps[1].parse().ok()?, caps[2].parse().ok()?))
+    }
+
+    fn parse_similar_images(
+        header: ElementRef<'_>,
+        thumbnail_dict: &std::collections::HashMap<String, String>,
+    ) -> (Vec<SearchResult>, Option<String>) {
+        let link_selector = Selector::parse("a[href]").unwrap();
+        let img_selector = Selector::parse("img").unwrap();
+
+        // 向上找到同时包含标题和图片的区块
+        let section = header
+            .ancestors()
+            .filter_map(ElementRef::wrap)
+            .take(6)
+            .find(|el| el.select(&img_selector).next().is_some());
+        let Some(section) = section else {
+            return (Vec::new(), None);
+        };
+
+        let mut images = Vec::new();
+        let mut similar_url = None;
+        for link in section.select(&link_selector) {
+            let href = link.value().attr("href").unwrap_or_default();
+            if href.contains("tbs=simg") && similar_url.is_none() {
+                similar_url = Some(Self::absolute_url(href));
+            }
+
+            let Some(img) = link.select(&img_selector).next() else {
+                continue;
+            };
+
+            let query = href.split_once('?').map(|(_, q)| q).unwrap_or_default();
+            let params: std::collections::HashMap<String, String> =
+                url::form_urlencoded::parse(query.as_bytes())
+                    .into_owned()
+                    .collect();
+
+            let thumbnail = img
+                .value()
+                .attr("id")
+                .and_then(|id| thumbnail_dict.get(id).cloned())
+                .or_else(|| {
+                    img.value()
+                        .attr("data-src")
+                        .or(img.value().attr("src"))
+                        .map(ToOwned::to_owned)
+                });
+            let size = params
+                .get("w")
+                .zip(params.get("h"))
+                .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)));
+
+            images.push(SearchResult {
+                title: img.value().attr("alt").map(ToOwned::to_owned),
+                url: params
+                    .get("imgrefurl")
+                    .cloned()
+                    .unwrap_or_else(|| Self::absolute_url(href)),
+                thumbnail,
+                source: "Google".to_string(),
+                index: Some(images.len().to_string()),
+                additional_info: Some(AdditionalInfo {
+                    source_url: params.get("imgurl").cloned(),
+                    size,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            });
+        }
+
+        (images, similar_url)
+    }
+
+    fn absolute_url(href: &str) -> String {
+        if href.starts_with('/') {
+            format!("{}{}", API_URL, href)
+        } else {
+            href.to_string()
+        }
+    }
+
     pub async fn next_page(&self, response: &GoogleResponse) -> Result<Option<GoogleResponse>> {
         let next_page = response.current_page + 1;
         if next_page > response.pages.len() {
             return Ok(None);
         }
 
-        let client = Self::build_client()?;
-        let next_url = &response.pages[next_page - 1];
-        let resp = client.get(next_url).send().await?;
-
-        if resp.status().is_success() {
-            let parsed = self
-                .parse_response(&resp.text().await?, next_url, next_page)
-                .await?;
-            Ok(Some(parsed))
-        } else {
-            Ok(None)
-        }
+        self.fetch_page(response, next_page).await
     }
 
     pub async fn prev_page(&self, response: &GoogleResponse) -> Result<Option<GoogleResponse>> {
@@ -197,78 +404,220 @@ impl Google {
             return Ok(None);
         }
 
-        let prev_page = response.current_page - 1;
-        let client = Self::build_client()?;
-        let prev_url = &response.pages[prev_page - 1];
-        let resp = client.get(prev_url).send().await?;
+        self.fetch_page(response, response.current_page - 1).await
+    }
 
-        if resp.status().is_success() {
-            let parsed = self
-                .parse_response(&resp.text().await?, prev_url, prev_page)
-                .await?;
-            Ok(Some(parsed))
-        } else {
-            Ok(None)
+    async fn fetch_page(
+        &self,
+        response: &GoogleResponse,
+        page: usize,
+    ) -> Result<Option<GoogleResponse>> {
+        let page_url = &response.pages[page - 1];
+        let network = self.network()?;
+        let resp = network
+            .send(
+                network
+                    .client()
+                    .get(page_url)
+                    .header(reqwest::header::REFERER, &response.url),
+            )
+            .await?;
+
+        if !resp.status().is_success() {
+            return Ok(None);
         }
+
+        let html = network.read_text(resp).await?;
+        let mut parsed = Self::parse_response(&html, page_url, page)?;
+
+        // 每一页只列出附近的页码，这里以首页的列表为准，追加新出现的页码，
+        // 保证 current_page 始终指向同一份列表
+        let mut pages = response.pages.clone();
+        let mut keys: HashSet<String> = pages.iter().map(|url| Self::page_key(url)).collect();
+        for url in parsed.pages.drain(..) {
+            // 首页总是列表中的第一项，后续页上指回首页的链接写法不同，直接跳过
+            if Self::page_start(&url) == Some(0) {
+                continue;
+            }
+            if keys.insert(Self::page_key(&url)) {
+                pages.push(url);
+            }
+        }
+        parsed.pages = pages;
+
+        Ok(Some(parsed))
     }
-}
 
-#[async_trait]
-impl ImageSearch for Google {
-    fn name(&self) -> &'static str {
-        "Google"
+    /// 分页链接的比较键，去掉每次请求都会变化的点击跟踪参数
+    fn page_key(url: &str) -> String {
+        let Some(mut parsed) = canonical::normalize(url).and_then(|url| Url::parse(&url).ok())
+        else {
+            return url.to_string();
+        };
+        let query: Vec<(String, String)> = parsed
+            .query_pairs()
+            .filter(|(key, _)| !matches!(key.as_ref(), "ved" | "ei" | "sa"))
+            .map(|(key, value)| (key.into_owned(), value.into_owned()))
+            .collect();
+        parsed.query_pairs_mut().clear().extend_pairs(query);
+        parsed.to_string()
     }
 
-    async fn search_url(
-        &self,
-        url: &str,
-        _options: &SearchOptions,
-    ) -> Result<(String, Vec<SearchResult>)> {
-        let client = Self::build_client()?;
-        let response = self.search_with_client(&client, url).await?;
-        Ok((response.url, response.results))
+    /// 分页链接的 `start` 参数，即该页第一条结果的序号，首页为 0
+    fn page_start(url: &str) -> Option<usize> {
+        let parsed = Url::parse(url).ok()?;
+        let (_, start) = parsed.query_pairs().find(|(key, _)| key == "start")?;
+        start.parse().ok()
     }
 
-    async fn search_bytes(
+    /// 从已获取的第一页开始逐页产出结果，直到没有下一页或达到 `limit`
+    pub fn paginate(
         &self,
-        bytes: &[u8],
-        _options: &SearchOptions,
-    ) -> Result<(String, Vec<SearchResult>)> {
-        let client = Self::build_client()?;
+        first: GoogleResponse,
+        limit: PageLimit,
+    ) -> impl Stream<Item = Result<GoogleResponse>> + '_ {
+        stream::unfold(Some(PageState::First(first)), move |state| async move {
+            let (mut page, pages, results) = match state? {
+                PageState::First(first) => (first, 0, 0),
+                PageState::Next {
+                    last,
+                    pages,
+                    results,
+                } => {
+                    if limit.max_pages.is_some_and(|max| pages >= max)
+                        || limit.max_results.is_some_and(|max| results >= max)
+                    {
+                        return None;
+                    }
+                    match self.next_page(&last).await {
+                        Ok(Some(page)) => (page, pages, results),
+                        Ok(None) => return None,
+                        Err(e) => return Some((Err(e), None)),
+                    }
+                }
+            };
+
+            if limit.max_pages.is_some_and(|max| pages >= max) {
+                return None;
+            }
+            if let Some(max) = limit.max_results {
+                page.results.truncate(max.saturating_sub(results));
+            }
+            // 空页说明已经没有更多结果
+            if page.results.is_empty() && pages > 0 {
+                return None;
+            }
+
+            let next = PageState::Next {
+                last: GoogleResponse {
+                    pages: page.pages.clone(),
+                    current_page: page.current_page,
+                    url: page.url.clone(),
+                    ..Default::default()
+                },
+                pages: pages + 1,
+                results: results + page.results.len(),
+            };
+            Some((Ok(page), Some(next)))
+        })
+    }
+
+    /// 通过图片 URL 搜索并以流的形式逐页返回结果
+    pub fn search_url_paginated<'a>(
+        &'a self,
+        url: &'a str,
+        limit: PageLimit,
+    ) -> impl Stream<Item = Result<GoogleResponse>> + 'a {
+        stream::once(self.search_url_page(url))
+            .map_ok(move |first| self.paginate(first, limit))
+            .try_flatten()
+    }
+
+    /// 上传图片搜索并以流的形式逐页返回结果
+    pub fn search_bytes_paginated<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        limit: PageLimit,
+    ) -> impl Stream<Item = Result<GoogleResponse>> + 'a {
+        stream::once(self.search_bytes_page(bytes))
+            .map_ok(move |first| self.paginate(first, limit))
+            .try_flatten()
+    }
+
+    /// 上传图片搜索，返回包含分页信息的第一页结果
+    pub async fn search_bytes_page(&self, bytes: &[u8]) -> Result<GoogleResponse> {
+        let network = self.network()?;
+        let client = network.client();
 
         // 先访问主页获取 cookie
-        client.get(API_URL).send().await?;
+        network.get(API_URL).await?;
 
         // 构建 multipart 表单
-        let part = multipart::Part::bytes(bytes.to_vec())
-            .file_name("image.jpg")
-            .mime_str("image/jpeg")?;
+        let image = preprocess::prepare(bytes, &self.upload_constraints())
+            .map_err(|e| e.with_engine(self.name()))?;
+        let part = image.into_part();
 
-        let form = multipart::Form::new()
+        let form = Form::new()
             .part("encoded_image", part)
             .text("image_content", "");
 
         // 发送上传请求
-        let response = client
-            .post(&format!("{}/searchbyimage/upload", API_URL))
-            .query(&[("hl", "en"), ("gl", "us")])
-            .header(reqwest::header::REFERER, API_URL)
-            .multipart(form)
-            .send()
+        let response = network
+            .send_form(
+                client
+                    .post(format!("{}/searchbyimage/upload", API_URL))
+                    .query(&[("hl", "en"), ("gl", "us")])
+                    .header(reqwest::header::REFERER, API_URL),
+                &form,
+            )
             .await?;
 
         if !response.status().is_success() {
-            return Err(Error::Engine(format!(
-                "上传失败: 状态码 {}",
-                response.status()
-            )));
+            return Err(Error::http_status(ENGINE, response).await);
         }
 
         let search_url = response.url().to_string();
-        let google_response = self
-            .parse_response(&response.text().await?, &search_url, 1)
-            .await?;
-        Ok((google_response.url, google_response.results))
+        let html = network.read_text(response).await?;
+        Self::parse_response(&html, &search_url, 1)
+    }
+}
+
+#[async_trait]
+impl ImageSearch for Google {
+    fn name(&self) -> &'static str {
+        ENGINE
+    }
+
+    fn upload_constraints(&self) -> UploadConstraints {
+        UploadConstraints {
+            formats: &[
+                ImageFormat::Jpeg,
+                ImageFormat::Png,
+                ImageFormat::Gif,
+                ImageFormat::WebP,
+                ImageFormat::Bmp,
+            ],
+            max_dimension: None,
+            max_bytes: Some(20 * 1024 * 1024),
+        }
+    }
+
+    async fn search_url(
+        &self,
+        url: &str,
+        _options: &SearchOptions,
+    ) -> Result<(String, Vec<SearchResult>)> {
+        let response = self.search_url_page(url).await?;
+        Ok((response.url, response.results))
+    }
+
+    async fn search_bytes(
+        &self,
+        bytes: &[u8],
+        _options: &SearchOptions,
+    ) -> Result<(String, Vec<SearchResult>)> {
+        let response = self.search_bytes_page(bytes).await?;
+        Ok((response.url, response.results))
     }
 
     async fn search_file(
@@ -1,19 +1,38 @@
 use super::ImageSearch;
 use crate::error::{Error, Result};
+use crate::network::{Form, Network};
+use crate::preprocess::{self, UploadConstraints};
 use crate::types::{AdditionalInfo, SearchOptions, SearchResult};
 use async_trait::async_trait;
+use image::ImageFormat;
 use regex;
-use reqwest::multipart;
 use scraper::{Html, Selector};
 use serde_json::Value;
 
 const API_URL: &str = "https://lens.google.com";
+const ENGINE: &str = "Google Lens";
 
-pub struct GoogleLens {}
+pub struct GoogleLens {
+    base_url: String,
+}
+
+impl Default for GoogleLens {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl GoogleLens {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            base_url: API_URL.to_string(),
+        }
+    }
+
+    /// 使用其他地址，例如本地的测试服务
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
     }
 
     fn build_client(redirect: bool) -> Result<reqwest::Client> {
@@ -46,7 +65,84 @@ impl GoogleLens {
         builder.build().map_err(Error::from)
     }
 
-    fn get_prerender_script(&self, html: &str) -> Result<Value> {
+    /// 每次搜索使用新的 cookie 会话，限速在所有会话之间共享
+    fn build_network(redirect: bool) -> Result<Network> {
+        Ok(Network::with_client(Self::build_client(redirect)?).for_engine(ENGINE))
+    }
+
+    /// 解析结果页中的最佳匹配与视觉匹配结果
+    pub fn parse_results(html: &str) -> Result<Vec<SearchResult>> {
+        let prerender_script = Self::get_prerender_script(html)?;
+        let data = Self::parse_prerender_script(prerender_script)?;
+
+        let mut results = Vec::new();
+
+        // 添加最佳匹配
+        if let Some(best_match) = data.get("match").filter(|m| !m.is_null()) {
+            results.push(SearchResult {
+                title: best_match
+                    .get("title")
+                    .and_then(|v| v.as_str())
+                    .map(String::from),
+                url: best_match
+                    .get("pageURL")
+                    .and_then(|v| v.as_str())
+                    .map(String::from)
+                    .unwrap_or_default(),
+                thumbnail: best_match
+                    .get("thumbnail")
+                    .and_then(|v| v.as_str())
+                    .map(String::from),
+                similarity: Some(100.0),
+                source: "Google Lens".to_string(),
+                additional_info: Some(AdditionalInfo::default()),
+                ..Default::default()
+            });
+        }
+
+        // 添加相似结果
+        if let Some(similar) = data.get("similar").and_then(|v| v.as_array()) {
+            for item in similar {
+                let mut additional_info = AdditionalInfo::default();
+
+                if let Some(website) = item.get("sourceWebsite").and_then(|v| v.as_str()) {
+                    additional_info.site = Some(website.to_string());
+                }
+
+                if let Some(price) = item.get("price").and_then(|v| v.as_str()) {
+                    additional_info.tags.push(format!("价格: {}", price));
+                }
+
+                if let Some(currency) = item.get("currency").and_then(|v| v.as_str()) {
+                    additional_info.tags.push(format!("货币: {}", currency));
+                }
+
+                results.push(SearchResult {
+                    title: item.get("title").and_then(|v| v.as_str()).map(String::from),
+                    url: item
+                        .get("pageURL")
+                        .and_then(|v| v.as_str())
+                        .map(String::from)
+                        .unwrap_or_default(),
+                    thumbnail: item
+                        .get("thumbnail")
+                        .and_then(|v| v.as_str())
+                        .map(String::from),
+                    similarity: item
+                        .get("similarity score")
+                        .and_then(|v| v.as_f64())
+                        .map(|v| v as f32),
+                    source: "Google Lens".to_string(),
+                    additional_info: Some(additional_info),
+                    ..Default::default()
+                });
+            }
+        }
+
+        Ok(results)
+    }
+
+    fn get_prerender_script(html: &str) -> Result<Value> {
         let document = Html::parse_document(html);
         let script_selector = Selector::parse("script").unwrap();
 
@@ -60,7 +156,7 @@ impl GoogleLens {
                     || text.contains("key: 'ds:1'")
             })
             .map(|script| script.text().collect::<String>())
-            .ok_or_else(|| Error::Engine("未找到包含数据的脚本".to_string()))?;
+            .ok_or_else(|| Error::parse(ENGINE, "未找到包含数据的脚本"))?;
 
         // 尝试提取数据
         if let Some(start_index) = script_text.find("(function(){var m=") {
@@ -80,10 +176,10 @@ impl GoogleLens {
                 .split("};")
                 .next()
                 .map(|s| format!("{}}}", s))
-                .ok_or_else(|| Error::Engine("无法提取有效的 JSON 对象".to_string()))?;
+                .ok_or_else(|| Error::parse(ENGINE, "无法提取有效的 JSON 对象"))?;
 
             serde_json::from_str(&js_text)
-                .map_err(|e| Error::Engine(format!("JSON 解析失败: {}", e)))
+                .map_err(|e| Error::parse(ENGINE, format!("JSON 解析失败: {}", e)))
         } else if let Some(start_index) = script_text.find("AF_initDataCallback") {
             let js_text = &script_text[start_index..];
             if let Some(data_start) = js_text.find("data:") {
@@ -94,28 +190,28 @@ impl GoogleLens {
                         .trim_end_matches(',')
                         .to_string();
                     serde_json::from_str(&data_text)
-                        .map_err(|e| Error::Engine(format!("JSON 解析失败: {}", e)))
+                        .map_err(|e| Error::parse(ENGINE, format!("JSON 解析失败: {}", e)))
                 } else {
-                    Err(Error::Engine("无法找到数据结束位置".to_string()))
+                    Err(Error::parse(ENGINE, "无法找到数据结束位置"))
                 }
             } else if let Some(data_start) = js_text.find("[[") {
                 let js_text = &js_text[data_start..];
                 if let Some(end_index) = js_text.find("]]") {
-                    let data_text = format!("{}", &js_text[..end_index + 2]);
+                    let data_text = js_text[..end_index + 2].to_string();
                     serde_json::from_str(&data_text)
-                        .map_err(|e| Error::Engine(format!("JSON 解析失败: {}", e)))
+                        .map_err(|e| Error::parse(ENGINE, format!("JSON 解析失败: {}", e)))
                 } else {
-                    Err(Error::Engine("无法找到数据结束位置".to_string()))
+                    Err(Error::parse(ENGINE, "无法找到数据结束位置"))
                 }
             } else {
-                Err(Error::Engine("无法找到数据起始位置".to_string()))
+                Err(Error::parse(ENGINE, "无法找到数据起始位置"))
             }
         } else {
-            Err(Error::Engine("未找到有效的数据格式".to_string()))
+            Err(Error::parse(ENGINE, "未找到有效的数据格式"))
         }
     }
 
-    fn parse_prerender_script(&self, prerender_script: Value) -> Result<Value> {
+    fn parse_prerender_script(prerender_script: Value) -> Result<Value> {
         let mut data = serde_json::json!({
             "match": null,
             "similar": []
@@ -130,15 +226,11 @@ impl GoogleLens {
             .and_then(|v| v.get(0))
             .and_then(|v| v.get(0))
         {
-            if let Ok(match_data) = (|| -> Result<Value> {
-                Ok(serde_json::json!({
-                    "title": best_match[0],
-                    "thumbnail": best_match[2][0][0],
-                    "pageURL": best_match[2][0][4]
-                }))
-            })() {
-                data["match"] = match_data;
-            }
+            data["match"] = serde_json::json!({
+                "title": best_match[0],
+                "thumbnail": best_match[2][0][0],
+                "pageURL": best_match[2][0][4]
+            });
         }
 
         // 获取视觉匹配结果
@@ -212,7 +304,19 @@ impl GoogleLens {
 #[async_trait]
 impl ImageSearch for GoogleLens {
     fn name(&self) -> &'static str {
-        "Google Lens"
+        ENGINE
+    }
+
+    fn cache_key(&self) -> String {
+        self.base_url.clone()
+    }
+
+    fn upload_constraints(&self) -> UploadConstraints {
+        UploadConstraints {
+            formats: &[ImageFormat::Jpeg, ImageFormat::Png, ImageFormat::WebP],
+            max_dimension: None,
+            max_bytes: Some(20 * 1024 * 1024),
+        }
     }
 
     async fn search_url(
@@ -220,88 +324,21 @@ impl ImageSearch for GoogleLens {
         url: &str,
         _options: &SearchOptions,
     ) -> Result<(String, Vec<SearchResult>)> {
-        let client = Self::build_client(true)?;
-        let search_url = format!("{}/uploadbyurl?url={}&hl=en&gl=us", API_URL, url);
+        let network = Self::build_network(true)?;
+        let search_url = format!("{}/uploadbyurl?url={}&hl=en&gl=us", self.base_url, url);
 
-        let response = client
-            .get(&search_url)
-            .header(reqwest::header::REFERER, API_URL)
-            .send()
+        let response = network
+            .send(
+                network
+                    .client()
+                    .get(&search_url)
+                    .header(reqwest::header::REFERER, &self.base_url),
+            )
             .await?;
 
-        let html = response.text().await?;
+        let html = network.read_text(response).await?;
 
-        // 检查是否被重定向到验证页面
-        if html.contains("Our systems have detected unusual traffic") {
-            return Err(Error::Engine("Google 要求验证，请稍后再试".to_string()));
-        }
-
-        let prerender_script = self.get_prerender_script(&html)?;
-        let data = self.parse_prerender_script(prerender_script)?;
-
-        let mut results = Vec::new();
-
-        // 添加最佳匹配
-        if let Some(best_match) = data.get("match") {
-            results.push(SearchResult {
-                title: best_match
-                    .get("title")
-                    .and_then(|v| v.as_str())
-                    .map(String::from),
-                url: best_match
-                    .get("pageURL")
-                    .and_then(|v| v.as_str())
-                    .map(String::from)
-                    .unwrap_or_default(),
-                thumbnail: best_match
-                    .get("thumbnail")
-                    .and_then(|v| v.as_str())
-                    .map(String::from),
-                similarity: Some(100.0),
-                source: "Google Lens".to_string(),
-                index: None,
-                additional_info: Some(AdditionalInfo::default()),
-            });
-        }
-
-        // 添加相似结果
-        if let Some(similar) = data.get("similar").and_then(|v| v.as_array()) {
-            for item in similar {
-                let mut additional_info = AdditionalInfo::default();
-
-                if let Some(website) = item.get("sourceWebsite").and_then(|v| v.as_str()) {
-                    additional_info.source_url = Some(website.to_string());
-                }
-
-                if let Some(price) = item.get("price").and_then(|v| v.as_str()) {
-                    additional_info.tags.push(format!("价格: {}", price));
-                }
-
-                if let Some(currency) = item.get("currency").and_then(|v| v.as_str()) {
-                    additional_info.tags.push(format!("货币: {}", currency));
-                }
-
-                results.push(SearchResult {
-                    title: item.get("title").and_then(|v| v.as_str()).map(String::from),
-                    url: item
-                        .get("pageURL")
-                        .and_then(|v| v.as_str())
-                        .map(String::from)
-                        .unwrap_or_default(),
-                    thumbnail: item
-                        .get("thumbnail")
-                        .and_then(|v| v.as_str())
-                        .map(String::from),
-                    similarity: item
-                        .get("similarity score")
-                        .and_then(|v| v.as_f64())
-                        .map(|v| v as f32),
-                    source: "Google Lens".to_string(),
-                    index: None,
-                    additional_info: Some(additional_info),
-                });
-            }
-        }
+        let results = Self::parse_results(&html)?;
 
         Ok((search_url, results))
     }
@@ -311,60 +348,63 @@ impl ImageSearch for GoogleLens {
         bytes: &[u8],
         _options: &SearchOptions,
     ) -> Result<(String, Vec<SearchResult>)> {
-        let client = Self::build_client(false)?;
+        let network = Self::build_network(false)?;
+        let client = network.client();
 
         // 先访问主页获取初始 cookie
-        client.get(API_URL).send().await?;
+        network.get(&self.base_url).await?;
 
         // 构建 multipart 表单
-        let part = multipart::Part::bytes(bytes.to_vec())
-            .file_name("image.jpg")
-            .mime_str("image/jpeg")?;
+        let image = preprocess::prepare(bytes, &self.upload_constraints())
+            .map_err(|e| e.with_engine(self.name()))?;
+        let part = image.into_part();
 
-        let form = multipart::Form::new()
+        let form = Form::new()
             .part("encoded_image", part)
             .text("image_content", "");
 
         // 发送上传请求
-        let upload_url = format!("{}/upload", API_URL);
-        let response = client
-            .post(&upload_url)
-            .query(&[("hl", "en"), ("gl", "us")])
-            .header(reqwest::header::REFERER, API_URL)
-            .multipart(form)
-            .send()
+        let upload_url = format!("{}/upload", self.base_url);
+        let response = network
+            .send_form(
+                client
+                    .post(&upload_url)
+                    .query(&[("hl", "en"), ("gl", "us")])
+                    .header(reqwest::header::REFERER, &self.base_url),
+                &form,
+            )
             .await?;
 
-        // 检查是否是 302 重定向
-        if response.status() == reqwest::StatusCode::FOUND {
-            if let Some(location) = response.headers().get(reqwest::header::LOCATION) {
-                let search_url = location
-                    .to_str()
-                    .map_err(|e| Error::Engine(e.to_string()))?;
-
-                // 添加时间戳和浏览器尺寸参数
-                let search_url = if search_url.contains('?') {
-                    format!(
-                        "{}&qsubts={}&biw=1920&bih=911",
-                        search_url,
-                        std::time::SystemTime::now()
-                            .duration_since(std::time::UNIX_EPOCH)
-                            .unwrap()
-                            .as_millis()
-                    )
-                } else {
-                    format!(
-                        "{}?qsubts={}&biw=1920&bih=911",
-                        search_url,
-                        std::time::SystemTime::now()
-                            .duration_since(std::time::UNIX_EPOCH)
-                            .unwrap()
-                            .as_millis()
-                    )
-                };
+        // 上传成功时返回 302，Location 指向结果页
+        if response.status() != reqwest::StatusCode::FOUND {
+            return Err(Error::http_status(ENGINE, response).await);
+        }
+        let location = response
+            .headers()
+            .get(reqwest::header::LOCATION)
+            .ok_or_else(|| Error::parse(ENGINE, "重定向响应中缺少 Location 头"))?
+            .to_str()
+            .map_err(|e| Error::parse(ENGINE, e.to_string()))?
+            .to_string();
+        // 响应在读取正文前一直占用一个并发名额，跟随重定向前先释放，
+        // 否则同时进行的上传会各自占着名额等待对方
+        drop(response);
+
+        // 添加时间戳和浏览器尺寸参数
+        let search_url = format!(
+            "{}{}qsubts={}&biw=1920&bih=911",
+            location,
+            if location.contains('?') { '&' } else { '?' },
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_millis()
+        );
 
-                // 跟随重定向
-                let response = client
+        // 跟随重定向
+        let response = network
+            .send(
+                client
                     .get(&search_url)
                     .header(reqwest::header::REFERER, upload_url)
                     .header(
@@ -377,98 +417,15 @@ impl ImageSearch for GoogleLens {
                     .header("sec-fetch-mode", "navigate")
                     .header("sec-fetch-site", "same-origin")
                     .header("sec-fetch-user", "?1")
-                    .header("upgrade-insecure-requests", "1")
-                    .send()
-                    .await?;
-
-                let html = response.text().await?;
-
-                // 检查是否被重定向到验证页面
-                if html.contains("Our systems have detected unusual traffic") {
-                    return Err(Error::Engine("Google 要求验证，请稍后再试".to_string()));
-                }
+                    .header("upgrade-insecure-requests", "1"),
+            )
+            .await?;
 
-                let prerender_script = self.get_prerender_script(&html)?;
-                let data = self.parse_prerender_script(prerender_script)?;
-
-                // 处理搜索结果
-                let mut results = Vec::new();
-
-                // 添加最佳匹配
-                if let Some(best_match) = data.get("match") {
-                    results.push(SearchResult {
-                        title: best_match
-                            .get("title")
-                            .and_then(|v| v.as_str())
-                            .map(String::from),
-                        url: best_match
-                            .get("pageURL")
-                            .and_then(|v| v.as_str())
-                            .map(String::from)
-                            .unwrap_or_default(),
-                        thumbnail: best_match
-                            .get("thumbnail")
-                            .and_then(|v| v.as_str())
-                            .map(String::from),
-                        similarity: Some(100.0),
-                        source: "Google Lens".to_string(),
-                        index: None,
-                        additional_info: Some(AdditionalInfo::default()),
-                    });
-                }
+        let html = network.read_text(response).await?;
 
-                // 添加相似结果
-                if let Some(similar) = data.get("similar").and_then(|v| v.as_array()) {
-                    for item in similar {
-                        let mut additional_info = AdditionalInfo::default();
-
-                        if let Some(website) = item.get("sourceWebsite").and_then(|v| v.as_str()) {
-                            additional_info.source_url = Some(website.to_string());
-                        }
-
-                        if let Some(price) = item.get("price").and_then(|v| v.as_str()) {
-                            additional_info.tags.push(format!("价格: {}", price));
-                        }
-
-                        if let Some(currency) = item.get("currency").and_then(|v| v.as_str()) {
-                            additional_info.tags.push(format!("货币: {}", currency));
-                        }
-
-                        results.push(SearchResult {
-                            title: item.get("title").and_then(|v| v.as_str()).map(String::from),
-                            url: item
-                                .get("pageURL")
-                                .and_then(|v| v.as_str())
-                                .map(String::from)
-                                .unwrap_or_default(),
-                            thumbnail: item
-                                .get("thumbnail")
-                                .and_then(|v| v.as_str())
-                                .map(String::from),
-                            similarity: item
-                                .get("similarity score")
-                                .and_then(|v| v.as_f64())
-                                .map(|v| v as f32),
-                            source: "Google Lens".to_string(),
-                            index: None,
-                            additional_info: Some(additional_info),
-                        });
-                    }
-                }
+        let results = Self::parse_results(&html)?;
 
-                Ok((search_url, results))
-            } else {
-                Err(Error::Engine("重定向响应中缺少 Location 头".to_string()))
-            }
-        } else {
-            // 如果不是 302 重定向，返回错误
-            let status = response.status();
-            let text = response.text().await?;
-            Err(Error::Engine(format!(
-                "上传失败: 期望 302 重定向，但收到 {} - {}",
-                status, text
-            )))
-        }
+        Ok((search_url, results))
     }
 
     async fn search_base64(
@@ -1,180 +1,442 @@
 use super::ImageSearch;
 use crate::error::{Error, Result};
-use crate::network::Network;
-use crate::types::{AdditionalInfo, SearchOptions, SearchResult};
+use crate::network::{Form, Network};
+use crate::preprocess::{self, UploadConstraints};
+use crate::types::{AdditionalInfo, MatchTier, Rating, SearchOptions, SearchResult};
+use crate::utils::normalize_url;
 use async_trait::async_trait;
 use base64::engine::general_purpose::STANDARD;
 use base64::Engine;
-use reqwest::multipart;
-use scraper::{Html, Selector};
+use image::ImageFormat;
+use regex::Regex;
+use scraper::{ElementRef, Html, Selector};
+use std::sync::LazyLock;
 
 const API_URL: &str = "https://iqdb.org";
+const ENGINE: &str = "IQDB";
+const API_URL_3D: &str = "https://3d.iqdb.org";
+
+static TR_SELECTOR: LazyLock<Selector> = LazyLock::new(|| Selector::parse("tr").unwrap());
+static TH_SELECTOR: LazyLock<Selector> = LazyLock::new(|| Selector::parse("th").unwrap());
+static LINK_SELECTOR: LazyLock<Selector> = LazyLock::new(|| Selector::parse("td a[href]").unwrap());
+static IMG_SELECTOR: LazyLock<Selector> = LazyLock::new(|| Selector::parse("img").unwrap());
+static TABLES_SELECTOR: LazyLock<Selector> =
+    LazyLock::new(|| Selector::parse("#pages > div > table").unwrap());
+static MORE_SELECTOR: LazyLock<Selector> =
+    LazyLock::new(|| Selector::parse("#more1 .pages > div > table").unwrap());
+static ANCHOR_SELECTOR: LazyLock<Selector> = LazyLock::new(|| Selector::parse("a[href]").unwrap());
+static SIZE_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(\d+)\s*[×x]\s*(\d+)").unwrap());
+static RATING_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\[(\w+)\]").unwrap());
+
+/// IQDB 可选的数据源，对应表单中的 `service[]`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IqdbService {
+    Danbooru,
+    Konachan,
+    Yandere,
+    Gelbooru,
+    SankakuChannel,
+    EShuushuu,
+    Zerochan,
+    AnimePictures,
+    /// 以下两个仅在 3d.iqdb.org 上可用
+    ThreeDBooru,
+    IdolComplex,
+}
+
+impl IqdbService {
+    fn id(&self) -> u8 {
+        match self {
+            IqdbService::Danbooru => 1,
+            IqdbService::Konachan => 2,
+            IqdbService::Yandere => 3,
+            IqdbService::Gelbooru => 4,
+            IqdbService::SankakuChannel => 5,
+            IqdbService::EShuushuu => 6,
+            IqdbService::ThreeDBooru => 7,
+            IqdbService::IdolComplex => 9,
+            IqdbService::Zerochan => 11,
+            IqdbService::AnimePictures => 13,
+        }
+    }
+
+    pub fn is_3d(&self) -> bool {
+        matches!(self, IqdbService::ThreeDBooru | IqdbService::IdolComplex)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct IqdbOptions {
+    /// 要查询的数据源，为空时查询全部
+    pub services: Vec<IqdbService>,
+    /// 使用 3d.iqdb.org 查询真人图片；只选了 3D 数据源时会自动切换
+    pub three_d: bool,
+    /// 忽略颜色，仅按灰度匹配
+    pub force_gray: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct IqdbResponse {
+    pub results: Vec<SearchResult>,
+    /// 默认折叠的低相似度结果
+    pub more_results: Vec<SearchResult>,
+    /// IQDB 提供的 "more results" 链接
+    pub more_url: Option<String>,
+}
 
 pub struct Iqdb {
     network: Network,
+    options: IqdbOptions,
+}
+
+impl Default for Iqdb {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Iqdb {
     pub fn new() -> Self {
         Self {
-            network: Network::new(),
+            network: Network::new().for_engine(ENGINE),
+            options: IqdbOptions::default(),
         }
     }
 
-    async fn parse_results(&self, html: &str) -> Result<Vec<SearchResult>> {
-        let document = Html::parse_document(html);
-        let mut results = Vec::new();
+    pub fn with_options(mut self, options: IqdbOptions) -> Self {
+        self.options = options;
+        self
+    }
 
-        // 匹配结果表格
-        let tables_selector = Selector::parse("#pages > div > table").unwrap();
-        let tables: Vec<_> = document.select(&tables_selector).collect();
+    fn api_url(&self) -> &'static str {
+        if self.is_3d() {
+            API_URL_3D
+        } else {
+            API_URL
+        }
+    }
+
+    fn is_3d(&self) -> bool {
+        let services = &self.options.services;
+        self.options.three_d || (!services.is_empty() && services.iter().all(IqdbService::is_3d))
+    }
 
-        // 跳过第一个表格（上传的图片）
-        if tables.len() <= 1 {
-            return Ok(results);
+    /// 两个站点的数据源互不通用，混用时在请求前报错
+    fn check_services(&self) -> Result<()> {
+        let is_3d = self.is_3d();
+        let mismatched: Vec<_> = self
+            .options
+            .services
+            .iter()
+            .filter(|service| service.is_3d() != is_3d)
+            .collect();
+        if mismatched.is_empty() {
+            return Ok(());
         }
+        Err(Error::InvalidArgument(format!(
+            "数据源 {:?} 不能在 {} 上查询",
+            mismatched,
+            self.api_url()
+        )))
+    }
 
-        for table in tables.iter().skip(1) {
-            let mut tr_list: Vec<_> = table.select(&Selector::parse("tr").unwrap()).collect();
+    /// 在表单中加入数据源与灰度选项
+    fn apply_options(&self, mut form: Form) -> Form {
+        for service in &self.options.services {
+            form = form.text("service[]", service.id().to_string());
+        }
+        if self.options.force_gray {
+            form = form.text("forcegray", "on");
+        }
+        form
+    }
 
-            // 检查是否有匹配结果
-            let content = tr_list[0]
-                .select(&Selector::parse("th").unwrap())
-                .next()
-                .map(|th| th.text().collect::<String>());
+    /// 通过图片 URL 搜索，返回包含折叠结果的完整响应
+    pub async fn search_url_page(&self, url: &str) -> Result<IqdbResponse> {
+        self.check_services()?;
+        let form = self.apply_options(Form::new().text("url", url.to_string()));
+        self.post_form(&form).await
+    }
 
-            if content.as_deref() == Some("No relevant matches") {
-                continue;
-            }
+    /// 上传图片搜索，返回包含折叠结果的完整响应
+    pub async fn search_bytes_page(&self, bytes: &[u8]) -> Result<IqdbResponse> {
+        self.check_services()?;
+        let image = preprocess::prepare(bytes, &self.upload_constraints())
+            .map_err(|e| e.with_engine(self.name()))?;
+        let form = self.apply_options(Form::new().part("file", image.into_part()));
+        self.post_form(&form).await
+    }
 
-            // 如果有 th，需要跳过第一行
-            if content.is_some() {
-                tr_list = tr_list[1..].to_vec();
-            }
+    /// 获取 IQDB 的 "more results" 列表，优先使用页面中已折叠的结果
+    pub async fn more_results(&self, response: &IqdbResponse) -> Result<Vec<SearchResult>> {
+        if !response.more_results.is_empty() {
+            return Ok(response.more_results.clone());
+        }
+        let Some(more_url) = &response.more_url else {
+            return Ok(Vec::new());
+        };
 
-            if tr_list.len() < 4 {
-                continue;
-            }
+        let response = self.network.get(more_url).await?;
+        if !response.status().is_success() {
+            return Err(Error::http_status(ENGINE, response).await);
+        }
+
+        let html = self.network.read_text(response).await?;
+        let page = Self::parse_response(&html, self.api_url());
+        Ok(page.results.into_iter().chain(page.more_results).collect())
+    }
+
+    async fn post_form(&self, form: &Form) -> Result<IqdbResponse> {
+        let response = self.network.post_multipart(self.api_url(), form).await?;
 
-            // 提取 URL 和缩略图
-            let url = tr_list[0]
-                .select(&Selector::parse("td > a").unwrap())
-                .next()
-                .and_then(|a| a.value().attr("href"))
-                .map(|href| {
-                    if href.starts_with("//") {
-                        format!("https:{}", href)
-                    } else {
-                        href.to_string()
-                    }
-                });
-
-            let thumbnail = tr_list[0]
-                .select(&Selector::parse("td > a > img").unwrap())
-                .next()
-                .and_then(|img| img.value().attr("src"))
-                .map(|src| format!("https://iqdb.org{}", src));
-
-            // 提取来源
-            let source = tr_list[1]
-                .select(&Selector::parse("img").unwrap())
-                .next()
-                .and_then(|img| img.next_sibling())
-                .and_then(|text| text.value().as_text())
-                .map(|text| text.trim().to_string())
-                .unwrap_or_default();
-
-            // 提取尺寸
-            let size = tr_list[2]
-                .select(&Selector::parse("td").unwrap())
-                .next()
-                .map(|td| td.text().collect::<String>())
-                .unwrap_or_default();
-
-            // 提取相似度
-            let similarity = tr_list[3]
-                .select(&Selector::parse("td").unwrap())
-                .next()
-                .map(|td| td.text().collect::<String>())
-                .and_then(|text| {
-                    text.strip_suffix("% similarity")
-                        .and_then(|s| s.parse::<f32>().ok())
-                        .map(|n| n / 100.0)
-                });
-
-            if let Some(url) = url {
-                results.push(SearchResult {
-                    title: Some(format!("[{}] {}", source, size)),
-                    url,
-                    thumbnail,
-                    similarity,
-                    source: "IQDB".to_string(),
-                    index: Some(results.len().to_string()),
-                    additional_info: Some(AdditionalInfo::default()),
-                });
+        if !response.status().is_success() {
+            return Err(Error::http_status(ENGINE, response).await);
+        }
+
+        let html = self.network.read_text(response).await?;
+        Ok(Self::parse_response(&html, self.api_url()))
+    }
+
+    /// 解析结果页，`base_url` 用于补全相对链接（IQDB 或 3D IQDB 的地址）
+    pub fn parse_response(html: &str, base_url: &str) -> IqdbResponse {
+        let document = Html::parse_document(html);
+
+        let more_url = document
+            .select(&ANCHOR_SELECTOR)
+            .find(|a| {
+                a.text()
+                    .collect::<String>()
+                    .to_lowercase()
+                    .contains("more results")
+            })
+            .and_then(|a| a.value().attr("href"))
+            .filter(|href| !href.starts_with('#') && !href.starts_with("javascript"))
+            .map(|href| {
+                if href.starts_with("//") {
+                    format!("https:{}", href)
+                } else if href.starts_with('/') {
+                    format!("{}{}", base_url, href)
+                } else {
+                    href.to_string()
+                }
+            });
+
+        IqdbResponse {
+            // 第一个表格是上传的图片
+            results: Self::parse_tables(document.select(&TABLES_SELECTOR).skip(1), base_url),
+            more_results: Self::parse_tables(document.select(&MORE_SELECTOR), base_url),
+            more_url,
+        }
+    }
+
+    fn parse_tables<'a>(
+        tables: impl Iterator<Item = ElementRef<'a>>,
+        base_url: &str,
+    ) -> Vec<SearchResult> {
+        tables
+            .filter_map(|table| Self::parse_table(table, base_url))
+            .collect()
+    }
+
+    /// 解析单个结果表格，结构不符合预期时返回 `None` 而不是 panic
+    fn parse_table(table: ElementRef<'_>, base_url: &str) -> Option<SearchResult> {
+        let rows: Vec<_> = table.select(&TR_SELECTOR).collect();
+
+        // 表头为匹配等级，例如 "Best match"
+        let header = rows
+            .iter()
+            .find_map(|tr| tr.select(&TH_SELECTOR).next())
+            .map(|th| th.text().collect::<String>().trim().to_string());
+        let match_tier = match header.as_deref() {
+            Some("No relevant matches") | Some("Your image") => return None,
+            Some("Best match") => Some(MatchTier::Best),
+            Some("Additional match") => Some(MatchTier::Additional),
+            Some("Possible match") => Some(MatchTier::Possible),
+            _ => None,
+        };
+
+        let rows: Vec<_> = rows
+            .into_iter()
+            .filter(|tr| tr.select(&TH_SELECTOR).next().is_none())
+            .collect();
+
+        // 缩略图所在的行，链接指向原帖
+        let link = rows
+            .iter()
+            .find_map(|tr| tr.select(&LINK_SELECTOR).next())?;
+        let url = normalize_url(link.value().attr("href")?).ok()?;
+        let img = link.select(&IMG_SELECTOR).next();
+        let thumbnail = img.and_then(|img| img.value().attr("src")).map(|src| {
+            if src.starts_with('/') && !src.starts_with("//") {
+                format!("{}{}", base_url, src)
+            } else {
+                normalize_url(src).unwrap_or_else(|_| src.to_string())
             }
+        });
+        let alt = img
+            .and_then(|img| img.value().attr("alt"))
+            .unwrap_or_default();
+
+        let texts: Vec<String> = rows
+            .iter()
+            .map(|tr| tr.text().collect::<String>().trim().to_string())
+            .collect();
+
+        // 来源行以站点图标和站点名称开头，可能附带同一图片在其他站点的链接
+        let source_row = rows.iter().find(|tr| {
+            tr.select(&IMG_SELECTOR).next().is_some()
+                && !tr
+                    .select(&LINK_SELECTOR)
+                    .any(|a| a.select(&IMG_SELECTOR).next().is_some())
+        });
+        let source = source_row
+            .and_then(|tr| tr.select(&IMG_SELECTOR).next())
+            .and_then(|img| img.next_sibling())
+            .and_then(|text| text.value().as_text())
+            .map(|text| text.trim().to_string())
+            .unwrap_or_default();
+        let ext_urls = source_row
+            .map(|tr| {
+                tr.select(&LINK_SELECTOR)
+                    .filter_map(|a| a.value().attr("href"))
+                    .filter_map(|href| normalize_url(href).ok())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let size_text = texts.iter().find(|text| SIZE_REGEX.is_match(text));
+        let size = size_text
+            .and_then(|text| SIZE_REGEX.captures(text))
+            .and_then(|caps| Some((caps[1].parse().ok()?, caps[2].parse().ok()?)));
+        let rating = size_text
+            .and_then(|text| RATING_REGEX.captures(text))
+            .and_then(|caps| Self::parse_rating(&caps[1]))
+            .or_else(|| Self::parse_alt_field(alt, "Rating").and_then(|r| Self::parse_rating(&r)));
+
+        let similarity = texts.iter().find_map(|text| {
+            text.strip_suffix("% similarity")
+                .and_then(|s| s.trim().parse::<f32>().ok())
+        });
+
+        let tags = Self::parse_alt_field(alt, "Tags")
+            .map(|tags| tags.split_whitespace().map(ToOwned::to_owned).collect())
+            .unwrap_or_default();
+
+        let mut extra = std::collections::BTreeMap::new();
+        if let Some(score) = Self::parse_alt_field(alt, "Score") {
+            extra.insert("score".to_string(), score);
         }
 
-        Ok(results)
+        let title = match size {
+            Some((width, height)) => format!("[{}] {}×{}", source, width, height),
+            None => format!("[{}]", source),
+        };
+
+        Some(SearchResult {
+            title: Some(title),
+            additional_info: Some(AdditionalInfo {
+                tags,
+                size,
+                rating,
+                ext_urls,
+                post_id: Self::parse_post_id(&url),
+                match_tier,
+                extra,
+                ..Default::default()
+            }),
+            url,
+            thumbnail,
+            similarity,
+            source: "IQDB".to_string(),
+            ..Default::default()
+        })
+    }
+
+    fn parse_rating(text: &str) -> Option<Rating> {
+        match text.to_lowercase().as_str() {
+            "safe" | "s" | "g" => Some(Rating::Safe),
+            "ero" | "questionable" | "q" => Some(Rating::Questionable),
+            "explicit" | "e" => Some(Rating::Explicit),
+            _ => None,
+        }
+    }
+
+    /// 缩略图的 alt 形如 `Rating: s Score: 12 Tags: 1girl solo`
+    fn parse_alt_field(alt: &str, field: &str) -> Option<String> {
+        const FIELDS: [&str; 3] = ["Rating:", "Score:", "Tags:"];
+
+        let start = alt.find(&format!("{}:", field))? + field.len() + 1;
+        let rest = &alt[start..];
+        let end = FIELDS
+            .iter()
+            .filter_map(|f| rest.find(f))
+            .min()
+            .unwrap_or(rest.len());
+        let value = rest[..end].trim();
+        (!value.is_empty()).then(|| value.to_string())
+    }
+
+    /// 从各个 booru 的帖子链接中取出帖子 id
+    fn parse_post_id(url: &str) -> Option<String> {
+        let url = url::Url::parse(url).ok()?;
+        if let Some((_, id)) = url.query_pairs().find(|(key, _)| key == "id") {
+            return Some(id.into_owned());
+        }
+        url.path_segments()?
+            .rev()
+            .find(|segment| !segment.is_empty() && segment.chars().all(|c| c.is_ascii_digit()))
+            .map(ToOwned::to_owned)
+    }
+
+    fn filter_results(results: Vec<SearchResult>, options: &SearchOptions) -> Vec<SearchResult> {
+        results
+            .into_iter()
+            .filter(|result| options.allows(result))
+            .collect()
     }
 }
 
 #[async_trait]
 impl ImageSearch for Iqdb {
     fn name(&self) -> &'static str {
-        "IQDB"
+        ENGINE
+    }
+
+    fn upload_constraints(&self) -> UploadConstraints {
+        // 页面上注明的限制：8192 KB，7500x7500
+        UploadConstraints {
+            formats: &[ImageFormat::Jpeg, ImageFormat::Png, ImageFormat::Gif],
+            max_dimension: Some(7500),
+            max_bytes: Some(8192 * 1024),
+        }
+    }
+
+    fn cache_key(&self) -> String {
+        format!("{:?}", self.options)
     }
 
     async fn search_url(
         &self,
         url: &str,
-        _options: &SearchOptions,
+        options: &SearchOptions,
     ) -> Result<(String, Vec<SearchResult>)> {
-        // 构建表单数据
-        let form = multipart::Form::new().text("url", url.to_string());
-
-        // 发送请求
-        let response = self.network.post_multipart(API_URL, form).await?;
-
-        if !response.status().is_success() {
-            return Err(Error::Engine(format!(
-                "IQDB returned status code: {}",
-                response.status()
-            )));
-        }
-
-        let html = response.text().await?;
-        let results = self.parse_results(&html).await?;
-
-        Ok(("".to_string(), results))
+        let response = self.search_url_page(url).await?;
+        Ok((
+            "".to_string(),
+            Self::filter_results(response.results, options),
+        ))
     }
 
     async fn search_bytes(
         &self,
         bytes: &[u8],
-        _options: &SearchOptions,
+        options: &SearchOptions,
     ) -> Result<(String, Vec<SearchResult>)> {
-        // 构建 multipart 表单
-        let part = multipart::Part::bytes(bytes.to_vec())
-            .file_name("image.jpg")
-            .mime_str("image/jpeg")?;
-
-        let form = multipart::Form::new().part("file", part);
-
-        // 发送请求
-        let response = self.network.post_multipart(API_URL, form).await?;
-
-        if !response.status().is_success() {
-            return Err(Error::Engine(format!(
-                "上传失败: 状态码 {}",
-                response.status()
-            )));
-        }
-
-        let html = response.text().await?;
-        let results = self.parse_results(&html).await?;
-
-        Ok(("".to_string(), results))
+        let response = self.search_bytes_page(bytes).await?;
+        Ok((
+            "".to_string(),
+            Self::filter_results(response.results, options),
+        ))
     }
 
     async fn search_file(
@@ -1,5 +1,6 @@
 use crate::error::Result;
-use crate::types::{SearchOptions, SearchResult};
+use crate::preprocess::UploadConstraints;
+use crate::types::{SearchOptions, SearchResponse, SearchResult};
 use async_trait::async_trait;
 
 #[async_trait]
@@ -7,6 +8,19 @@ pub trait ImageSearch: Send + Sync {
     /// Get the name of the search engine
     fn name(&self) -> &'static str;
 
+    /// Formats and limits accepted for uploads; images are converted to fit before uploading
+    fn upload_constraints(&self) -> UploadConstraints {
+        UploadConstraints::default()
+    }
+
+    /// Fingerprint of the engine configuration that affects results, used in cache keys
+    ///
+    /// Engines with settings such as API keys, services or base URLs must override this so
+    /// differently configured instances with the same name do not share cached results
+    fn cache_key(&self) -> String {
+        String::new()
+    }
+
     /// Search for an image using its URL
     async fn search_url(
         &self,
@@ -42,6 +56,25 @@ pub trait ImageSearch: Send + Sync {
         let bytes = crate::utils::base64_to_bytes(base64)?;
         self.search_bytes(&bytes, options).await
     }
+
+    /// Search for an image using raw bytes, applying the opt-in `auto_crop` and `verify` steps
+    async fn search_image(&self, bytes: &[u8], options: &SearchOptions) -> Result<SearchResponse> {
+        let cropped = if options.auto_crop {
+            crate::preprocess::auto_crop(bytes)?
+        } else {
+            None
+        };
+        let searched = cropped.as_ref().map_or(bytes, |(cropped, _)| cropped);
+        let (page_url, mut results) = self.search_bytes(searched, options).await?;
+        if options.verify {
+            crate::verify::verify_results(searched, &mut results, options).await?;
+        }
+        Ok(SearchResponse {
+            page_url,
+            results,
+            crop: cropped.map(|(_, rect)| rect),
+        })
+    }
 }
 
 pub mod ascii2d;
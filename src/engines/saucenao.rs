@@ -1,12 +1,21 @@
 use super::ImageSearch;
 use crate::error::{Error, Result};
-use crate::network::Network;
+use crate::network::{Form, Network};
+use crate::preprocess::{self, UploadConstraints};
+use crate::retry;
 use crate::types::{AdditionalInfo, SearchOptions, SearchResult};
-use crate::utils::{base64_to_bytes, bytes_to_base64, normalize_url};
+use crate::utils::{base64_to_bytes, normalize_url};
 use async_trait::async_trait;
+use image::ImageFormat;
+use log::debug;
+use reqwest::Response;
 use serde::Deserialize;
 
 const API_URL: &str = "https://saucenao.com/search.php";
+const ENGINE: &str = "SauceNAO";
+
+/// `hide` 参数：2 为隐藏确定与疑似的成人内容（0 全部显示，1 只隐藏确定的，3 只显示确定安全的）
+const HIDE_EXPLICIT: &str = "2";
 
 pub struct SauceNao {
     network: Network,
@@ -16,10 +25,119 @@ pub struct SauceNao {
 impl SauceNao {
     pub fn new(api_key: Option<String>) -> Self {
         Self {
-            network: Network::new(),
+            network: Network::new().for_engine(ENGINE),
             api_key,
         }
     }
+
+    /// 解析 API 返回的 JSON，按 `options.min_similarity` 过滤结果
+    pub fn parse_json(json: &str, options: &SearchOptions) -> Result<Vec<SearchResult>> {
+        Self::convert_results(serde_json::from_str(json)?, options)
+    }
+
+    fn convert_results(
+        sauce_response: SauceNaoResponse,
+        options: &SearchOptions,
+    ) -> Result<Vec<SearchResult>> {
+        // status 小于 0 为请求本身的问题（API key 无效、次数用尽等）；大于 0 为部分索引
+        // 查询失败，只要还有结果就照常返回
+        let status = sauce_response.header.status;
+        let results = sauce_response.results.unwrap_or_default();
+        if status < 0 || (status > 0 && results.is_empty()) {
+            let message = sauce_response
+                .header
+                .message
+                .unwrap_or_else(|| "Unknown error".to_string());
+            if status < 0 {
+                if let Some(error) = Self::classify_message(&message) {
+                    return Err(error);
+                }
+            }
+            return Err(Error::Api {
+                engine: ENGINE,
+                code: status,
+                message,
+            });
+        }
+        if status > 0 {
+            debug!("部分索引查询失败 (status {})，返回其余结果", status);
+        }
+
+        let min_similarity = options.min_similarity.unwrap_or(0.0);
+
+        let results = results
+            .into_iter()
+            .filter(|item| item.header.similarity.parse::<f32>().unwrap_or(0.0) >= min_similarity)
+            .map(|item| {
+                let similarity = item.header.similarity.parse::<f32>().unwrap_or(0.0);
+                let mut urls = item.data.ext_urls.unwrap_or_default();
+                let source = item.data.source.clone();
+                let url = if urls.is_empty() {
+                    source.clone().unwrap_or_default()
+                } else {
+                    urls.remove(0)
+                };
+
+                SearchResult {
+                    title: item.data.title,
+                    url: normalize_url(&url).unwrap_or(url),
+                    thumbnail: Some(item.header.thumbnail),
+                    similarity: Some(similarity),
+                    source: item.header.index_name,
+                    index: Some(item.header.index_id.to_string()),
+                    additional_info: Some(AdditionalInfo {
+                        author: item.data.author_name,
+                        author_url: item.data.author_url,
+                        source_url: source,
+                        created_at: item.data.created_at,
+                        ext_urls: urls,
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }
+            })
+            .collect();
+
+        Ok(results)
+    }
+
+    async fn handle_response(
+        &self,
+        response: Response,
+        options: &SearchOptions,
+    ) -> Result<Vec<SearchResult>> {
+        let status = response.status();
+        if !status.is_success() {
+            let error = Error::http_status(ENGINE, response).await;
+            if let Error::HttpStatus { body_snippet, .. } = &error {
+                if let Some(error) = Self::classify_message(body_snippet) {
+                    return Err(error);
+                }
+            }
+            // 超出短时间内的搜索次数限制
+            if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+                return Err(Error::RateLimit { engine: ENGINE });
+            }
+            return Err(error);
+        }
+
+        let json = self.network.read_text(response).await?;
+        Self::parse_json(&json, options)
+    }
+
+    /// 根据 SauceNAO 的提示信息识别 API key 无效与每日额度用尽
+    fn classify_message(message: &str) -> Option<Error> {
+        let message = message.to_lowercase();
+        if message.contains("api key")
+            && (message.contains("invalid") || message.contains("not valid"))
+        {
+            Some(Error::InvalidApiKey { engine: ENGINE })
+        } else if message.contains("daily search limit") {
+            Some(Error::QuotaExhausted { engine: ENGINE })
+        } else {
+            None
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -52,6 +170,7 @@ struct ResultHeader {
 struct ResultData {
     ext_urls: Option<Vec<String>>,
     title: Option<String>,
+    #[serde(alias = "member_name")]
     author_name: Option<String>,
     author_url: Option<String>,
     source: Option<String>,
@@ -61,7 +180,12 @@ struct ResultData {
 #[async_trait]
 impl ImageSearch for SauceNao {
     fn name(&self) -> &'static str {
-        "SauceNAO"
+        ENGINE
+    }
+
+    // 缓存只保存 API key 的哈希，不会写入明文
+    fn cache_key(&self) -> String {
+        self.api_key.clone().unwrap_or_default()
     }
 
     async fn search_url(
@@ -78,83 +202,43 @@ impl ImageSearch for SauceNao {
         if let Some(ref api_key) = self.api_key {
             params.push(("api_key", api_key.clone()));
         }
-
-        let client = reqwest::Client::builder()
-            .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/121.0.0.0 Safari/537.36")
-            .default_headers({
-                let mut headers = reqwest::header::HeaderMap::new();
-                headers.insert(reqwest::header::ACCEPT, "text/html,application/xhtml+xml,application/xml;q=0.9,image/avif,image/webp,*/*;q=0.8".parse().unwrap());
-                headers.insert(reqwest::header::ACCEPT_LANGUAGE, "zh-CN,zh;q=0.9,en-US;q=0.8,en;q=0.7".parse().unwrap());
-                headers.insert(reqwest::header::REFERER, "https://saucenao.com/".parse().unwrap());
-                headers
-            })
-            .build()?;
-
-        let search_url = format!("{}?url={}", API_URL, url);
-
-        let response = client
-            .get(&format!(
-                "{}?{}",
-                API_URL,
-                url::form_urlencoded::Serializer::new(String::new())
-                    .extend_pairs(params)
-                    .finish()
-            ))
-            .send()
-            .await?;
-
-        if !response.status().is_success() {
-            return Err(Error::Engine(format!(
-                "SauceNAO returned status code: {}",
-                response.status()
-            )));
-        }
-
-        let sauce_response: SauceNaoResponse = response.json().await?;
-
-        if sauce_response.header.status < 0 {
-            return Err(Error::Engine(
-                sauce_response
-                    .header
-                    .message
-                    .unwrap_or_else(|| "Unknown error".to_string()),
-            ));
+        if options.hide_explicit {
+            params.push(("hide", HIDE_EXPLICIT.to_string()));
         }
 
-        let results = sauce_response.results.unwrap_or_default();
-        let min_similarity = options.min_similarity.unwrap_or(0.0);
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert(
+            reqwest::header::ACCEPT,
+            "text/html,application/xhtml+xml,application/xml;q=0.9,image/avif,image/webp,*/*;q=0.8"
+                .parse()
+                .unwrap(),
+        );
+        headers.insert(
+            reqwest::header::ACCEPT_LANGUAGE,
+            "zh-CN,zh;q=0.9,en-US;q=0.8,en;q=0.7".parse().unwrap(),
+        );
+        headers.insert(
+            reqwest::header::REFERER,
+            "https://saucenao.com/".parse().unwrap(),
+        );
 
-        let results = results
-            .into_iter()
-            .filter(|item| item.header.similarity.parse::<f32>().unwrap_or(0.0) >= min_similarity)
-            .map(|item| {
-                let similarity = item.header.similarity.parse::<f32>().unwrap_or(0.0);
-                let mut urls = item.data.ext_urls.unwrap_or_default();
-                let source = item.data.source.clone();
-                let url = if urls.is_empty() {
-                    source.clone().unwrap_or_default()
-                } else {
-                    urls.remove(0)
-                };
+        let search_url = format!("{}?url={}", API_URL, url);
 
-                SearchResult {
-                    title: item.data.title,
-                    url: normalize_url(&url).unwrap_or(url),
-                    thumbnail: Some(item.header.thumbnail),
-                    similarity: Some(similarity),
-                    source: item.header.index_name,
-                    index: Some(item.header.index_id.to_string()),
-                    additional_info: Some(AdditionalInfo {
-                        author: item.data.author_name,
-                        author_url: item.data.author_url,
-                        source_url: source,
-                        created_at: item.data.created_at,
-                        ext_urls: urls,
-                        ..Default::default()
-                    }),
-                }
-            })
-            .collect();
+        let request_url = format!(
+            "{}?{}",
+            API_URL,
+            url::form_urlencoded::Serializer::new(String::new())
+                .extend_pairs(params)
+                .finish()
+        );
+        let results = retry::retry(&retry::policy(self.name()), || async {
+            let response = self
+                .network
+                .get_with_headers(&request_url, headers.clone())
+                .await?;
+            self.handle_response(response, options).await
+        })
+        .await?;
 
         Ok((search_url, results))
     }
@@ -164,79 +248,9 @@ impl ImageSearch for SauceNao {
         base64: &str,
         options: &SearchOptions,
     ) -> Result<(String, Vec<SearchResult>)> {
-        let mut form = reqwest::multipart::Form::new()
-            .text("output_type", "2")
-            .text("numres", "16")
-            .text("api_key", self.api_key.clone().unwrap_or_default())
-            .text("dbmask", "999") // 使用所有数据库
-            .text("minsim", options.min_similarity.unwrap_or(80.0).to_string());
-
         // 将 base64 转换回二进制
         let image_data = base64_to_bytes(base64)?;
-
-        // 添加文件部分
-        let part = reqwest::multipart::Part::bytes(image_data)
-            .file_name("image.png")
-            .mime_str("image/png")?;
-        form = form.part("file", part);
-
-        let response = self.network.post_multipart(API_URL, form).await?;
-
-        if !response.status().is_success() {
-            return Err(Error::Engine(format!(
-                "SauceNAO returned status code: {} - {}",
-                response.status(),
-                response.text().await?
-            )));
-        }
-
-        let sauce_response: SauceNaoResponse = response.json().await?;
-
-        if sauce_response.header.status < 0 {
-            return Err(Error::Engine(
-                sauce_response
-                    .header
-                    .message
-                    .unwrap_or_else(|| "Unknown error".to_string()),
-            ));
-        }
-
-        let results = sauce_response.results.unwrap_or_default();
-        let min_similarity = options.min_similarity.unwrap_or(0.0);
-
-        let results = results
-            .into_iter()
-            .filter(|item| item.header.similarity.parse::<f32>().unwrap_or(0.0) >= min_similarity)
-            .map(|item| {
-                let similarity = item.header.similarity.parse::<f32>().unwrap_or(0.0);
-                let mut urls = item.data.ext_urls.unwrap_or_default();
-                let source = item.data.source.clone();
-                let url = if urls.is_empty() {
-                    source.clone().unwrap_or_default()
-                } else {
-                    urls.remove(0)
-                };
-
-                SearchResult {
-                    title: item.data.title,
-                    url: normalize_url(&url).unwrap_or(url),
-                    thumbnail: Some(item.header.thumbnail),
-                    similarity: Some(similarity),
-                    source: item.header.index_name,
-                    index: Some(item.header.index_id.to_string()),
-                    additional_info: Some(AdditionalInfo {
-                        author: item.data.author_name,
-                        author_url: item.data.author_url,
-                        source_url: source,
-                        created_at: item.data.created_at,
-                        ext_urls: urls,
-                        ..Default::default()
-                    }),
-                }
-            })
-            .collect();
-
-        Ok(("".to_string(), results))
+        self.search_bytes(&image_data, options).await
     }
 
     async fn search_bytes(
@@ -244,85 +258,41 @@ impl ImageSearch for SauceNao {
         bytes: &[u8],
         options: &SearchOptions,
     ) -> Result<(String, Vec<SearchResult>)> {
-        self.search_base64(&bytes_to_base64(bytes), options).await
-    }
+        let image = preprocess::prepare(bytes, &self.upload_constraints())
+            .map_err(|e| e.with_engine(self.name()))?;
 
-    async fn search_file(
-        &self,
-        file_path: &str,
-        options: &SearchOptions,
-    ) -> Result<(String, Vec<SearchResult>)> {
-        let mut form = reqwest::multipart::Form::new()
+        let mut form = Form::new()
             .text("output_type", "2")
             .text("numres", "16")
             .text("api_key", self.api_key.clone().unwrap_or_default())
             .text("dbmask", "999") // 使用所有数据库
             .text("minsim", options.min_similarity.unwrap_or(80.0).to_string());
+        if options.hide_explicit {
+            form = form.text("hide", HIDE_EXPLICIT);
+        }
 
         // 添加文件部分
-        let part = reqwest::multipart::Part::bytes(tokio::fs::read(file_path).await?)
-            .file_name("image.png")
-            .mime_str("image/png")?;
-        form = form.part("file", part);
-
-        let response = self.network.post_multipart(API_URL, form).await?;
-        // 打印response的url
-        println!("response的url: {}", response.url());
-
-        if !response.status().is_success() {
-            return Err(Error::Engine(format!(
-                "SauceNAO returned status code: {} - {}",
-                response.status(),
-                response.text().await?
-            )));
-        }
+        form = form.part("file", image.into_part());
 
-        let sauce_response: SauceNaoResponse = response.json().await?;
+        let results = retry::retry(&retry::policy(self.name()), || async {
+            let response = self.network.post_multipart(API_URL, &form).await?;
+            self.handle_response(response, options).await
+        })
+        .await?;
+        Ok(("".to_string(), results))
+    }
 
-        if sauce_response.header.status < 0 {
-            return Err(Error::Engine(
-                sauce_response
-                    .header
-                    .message
-                    .unwrap_or_else(|| "Unknown error".to_string()),
-            ));
+    fn upload_constraints(&self) -> UploadConstraints {
+        UploadConstraints {
+            formats: &[
+                ImageFormat::Jpeg,
+                ImageFormat::Png,
+                ImageFormat::Gif,
+                ImageFormat::WebP,
+                ImageFormat::Bmp,
+            ],
+            max_dimension: None,
+            max_bytes: Some(15 * 1024 * 1024),
         }
-
-        let results = sauce_response.results.unwrap_or_default();
-        let min_similarity = options.min_similarity.unwrap_or(0.0);
-
-        let results = results
-            .into_iter()
-            .filter(|item| item.header.similarity.parse::<f32>().unwrap_or(0.0) >= min_similarity)
-            .map(|item| {
-                let similarity = item.header.similarity.parse::<f32>().unwrap_or(0.0);
-                let mut urls = item.data.ext_urls.unwrap_or_default();
-                let source = item.data.source.clone();
-                let url = if urls.is_empty() {
-                    source.clone().unwrap_or_default()
-                } else {
-                    urls.remove(0)
-                };
-
-                SearchResult {
-                    title: item.data.title,
-                    url: normalize_url(&url).unwrap_or(url),
-                    thumbnail: Some(item.header.thumbnail),
-                    similarity: Some(similarity),
-                    source: item.header.index_name,
-                    index: Some(item.header.index_id.to_string()),
-                    additional_info: Some(AdditionalInfo {
-                        author: item.data.author_name,
-                        author_url: item.data.author_url,
-                        source_url: source,
-                        created_at: item.data.created_at,
-                        ext_urls: urls,
-                        ..Default::default()
-                    }),
-                }
-            })
-            .collect();
-
-        Ok(("".to_string(), results))
     }
 }
@@ -1,49 +1,176 @@
+use super::ImageSearch;
 use crate::error::Error;
 use crate::error::Result;
-use crate::types::{SearchOptions, SearchResult};
+use crate::network::{Form, Network, MAX_DOWNLOAD_SIZE};
+use crate::preprocess::{self, UploadConstraints};
+use crate::types::{AdditionalInfo, Rating, SearchOptions, SearchResult};
 use async_trait::async_trait;
 use base64::{engine::general_purpose, Engine as _};
-use reqwest::header::HeaderValue;
-use reqwest::multipart::{Form, Part};
+use image::ImageFormat;
+use reqwest::header::{HeaderMap, HeaderValue};
 use serde::{Deserialize, Serialize};
 use std::time::{SystemTime, UNIX_EPOCH};
 
+const API_URL: &str = "https://soutubot.moe/api/search";
+const ENGINE: &str = "Soutubot";
+
+/// 网页版默认的匹配系数，越大越严格
+pub const DEFAULT_FACTOR: f32 = 1.2;
+
+/// 一次 Soutubot 搜索的完整结果，`id` 可用于以其他系数重新搜索
+#[derive(Debug, Clone)]
+pub struct SoutubotSearch {
+    pub id: String,
+    pub factor: f32,
+    /// 服务端回显的搜索参数，例如 `api 1.4 Liner 64`
+    pub search_option: String,
+    /// Soutubot 保存的上传图片地址
+    pub image_url: String,
+    pub page_url: String,
+    pub results: Vec<SearchResult>,
+}
+
 pub struct Soutubot {
-    client: reqwest::Client,
+    factor: f32,
+}
+
+impl Default for Soutubot {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Soutubot {
-    pub fn new(client: reqwest::Client) -> Self {
-        Self { client }
+    pub fn new() -> Self {
+        Self {
+            factor: DEFAULT_FACTOR,
+        }
+    }
+
+    /// 设置匹配系数，较小的值会返回更多模糊匹配；必须为正数，否则搜索时返回 `Error::InvalidArgument`
+    pub fn with_factor(mut self, factor: f32) -> Self {
+        self.factor = factor;
+        self
+    }
+
+    /// 上传图片搜索，返回包含搜索 id 与系数的完整结果
+    pub async fn search_bytes_page(
+        &self,
+        bytes: &[u8],
+        options: &SearchOptions,
+    ) -> Result<SoutubotSearch> {
+        check_factor(self.factor)?;
+        let image = preprocess::prepare(bytes, &self.upload_constraints())
+            .map_err(|e| e.with_engine(self.name()))?;
+        let form = Form::new()
+            .text("factor", self.factor.to_string())
+            .part("file", image.into_part());
+        Self::search(&form, options).await
+    }
+
+    /// 以新的系数重新搜索已上传的图片，无需再次上传
+    pub async fn research(
+        &self,
+        id: &str,
+        factor: f32,
+        options: &SearchOptions,
+    ) -> Result<SoutubotSearch> {
+        check_factor(factor)?;
+        let form = Form::new()
+            .text("factor", factor.to_string())
+            .text("id", id.to_string());
+        Self::search(&form, options).await
+    }
+
+    /// 按搜索选项的代理与超时发送请求，限速与磁带按 Soutubot 计算
+    async fn search(form: &Form, options: &SearchOptions) -> Result<SoutubotSearch> {
+        let network = Network::from_options(options)?.for_engine(ENGINE);
+        // 发送请求
+        let response = network
+            .post_multipart_with_headers(API_URL, form, build_headers()?)
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(Error::http_status(ENGINE, response).await);
+        }
+
+        let json = network.read_text(response).await?;
+        Self::parse_json(&json)
+    }
+
+    /// 解析搜索接口返回的 JSON
+    pub fn parse_json(json: &str) -> Result<SoutubotSearch> {
+        let soutubot_response: SoutubotResponse = serde_json::from_str(json)?;
+        if soutubot_response.code != 0 {
+            return Err(Error::Api {
+                engine: ENGINE,
+                code: soutubot_response.code,
+                message: soutubot_response.message,
+            });
+        }
+        // json格式 {"data":[],"id":"2025020814015112","factor":1.4,"imageUrl":"https:\/\/img.76888268.xyz\/img\/8abba7d56ebab7885b2a68cf0d57c742.webp","searchOption":"api 1.4 Liner 64","executionTime":2.6}
+        Ok(SoutubotSearch {
+            // 结果页链接
+            page_url: format!("https://soutubot.moe/results/{}", soutubot_response.id),
+            id: soutubot_response.id,
+            factor: soutubot_response.factor,
+            search_option: soutubot_response.search_option,
+            image_url: soutubot_response.image_url,
+            // 转换结果
+            results: soutubot_response
+                .data
+                .into_iter()
+                .map(convert_result)
+                .collect(),
+        })
     }
 }
 
 #[async_trait]
-impl super::ImageSearch for Soutubot {
+impl ImageSearch for Soutubot {
     fn name(&self) -> &'static str {
-        "Soutubot"
+        ENGINE
+    }
+
+    fn upload_constraints(&self) -> UploadConstraints {
+        UploadConstraints {
+            formats: &[ImageFormat::Jpeg, ImageFormat::Png, ImageFormat::WebP],
+            max_dimension: None,
+            max_bytes: Some(10 * 1024 * 1024),
+        }
+    }
+
+    fn cache_key(&self) -> String {
+        format!("factor={}", self.factor)
     }
 
     async fn search_url(
         &self,
         url: &str,
-        _options: &SearchOptions,
+        options: &SearchOptions,
     ) -> Result<(String, Vec<SearchResult>)> {
-        let bytes = reqwest::get(url).await?.bytes().await?;
-        self.search_bytes(&bytes, _options).await
+        // Soutubot 只支持上传文件，先通过网络层下载图片；下载的是其他站点，不占用 Soutubot 的限速
+        let bytes = Network::from_options(options)?
+            .download(url, MAX_DOWNLOAD_SIZE)
+            .await?;
+        self.search_bytes(&bytes, options).await
     }
 
     async fn search_bytes(
         &self,
         bytes: &[u8],
-        _options: &SearchOptions,
+        options: &SearchOptions,
     ) -> Result<(String, Vec<SearchResult>)> {
-        let results = search(&self.client, bytes).await?;
-        Ok(results)
+        let search = self.search_bytes_page(bytes, options).await?;
+        let results = search
+            .results
+            .into_iter()
+            .filter(|result| options.allows(result))
+            .collect();
+        Ok((search.page_url, results))
     }
 }
 
-
 #[derive(Debug, Serialize, Deserialize)]
 struct SoutubotResponse {
     #[serde(default)]
@@ -60,10 +187,10 @@ struct SoutubotResponse {
     search_option: String,
     #[serde(rename = "id", default)]
     id: String,
+    #[serde(default)]
+    factor: f32,
 }
 
-
-
 #[derive(Debug, Serialize, Deserialize)]
 struct SoutubotResult {
     #[serde(default)]
@@ -82,6 +209,17 @@ struct SoutubotResult {
     page_path: Option<String>,
 }
 
+fn check_factor(factor: f32) -> Result<()> {
+    if factor.is_finite() && factor > 0.0 {
+        Ok(())
+    } else {
+        Err(Error::InvalidArgument(format!(
+            "匹配系数必须为正数: {}",
+            factor
+        )))
+    }
+}
+
 const USER_AGENT: &str =
     "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/133.0.0.0 Safari/537.36";
 
@@ -113,95 +251,82 @@ fn apikey(ua: &str) -> String {
     reversed
 }
 
-pub async fn search(client: &reqwest::Client, image_bytes: &[u8]) -> Result<(String, Vec<SearchResult>)> {
+fn build_headers() -> Result<HeaderMap> {
     // 生成 API key
     let api_key = apikey(USER_AGENT);
 
-    // 构建请求头
-    let headers = {
-        let mut headers = reqwest::header::HeaderMap::new();
-        headers.insert("sec-ch-ua", HeaderValue::from_static(USER_AGENT));
-        headers.insert("dnt", HeaderValue::from_static("1"));
-        headers.insert("sec-ch-ua-mobile", HeaderValue::from_static("?0"));
-        headers.insert(
-            "accept",
-            HeaderValue::from_static("application/json, text/plain, */*"),
-        );
-        headers.insert(
-            "x-requested-with",
-            HeaderValue::from_static("XMLHttpRequest"),
-        );
-        headers.insert(
-            "x-api-key",
-            HeaderValue::from_str(&api_key).map_err(|e| Error::InvalidResponse(e.to_string()))?,
-        );
-        headers.insert(
-            "sec-ch-ua-platform",
-            HeaderValue::from_static("\"Windows\""),
-        );
-        headers.insert("origin", HeaderValue::from_static("https://soutubot.moe"));
-        headers.insert("sec-fetch-site", HeaderValue::from_static("same-origin"));
-        headers.insert("sec-fetch-mode", HeaderValue::from_static("cors"));
-        headers.insert("sec-fetch-dest", HeaderValue::from_static("empty"));
-        headers.insert("referer", HeaderValue::from_static("https://soutubot.moe/"));
-        headers.insert(
-            "accept-language",
-            HeaderValue::from_static("zh-CN,zh;q=0.9"),
-        );
-        headers
-    };
-
-    // 构建 multipart form
-    let form = Form::new().text("factor", "1.2").part(
-        "file",
-        Part::bytes(image_bytes.to_vec())
-            .file_name("image")
-            .mime_str("application/octet-stream")?,
+    let mut headers = HeaderMap::new();
+    // x-api-key 由 UA 长度计算，请求中的 UA 必须与之一致
+    headers.insert("user-agent", HeaderValue::from_static(USER_AGENT));
+    headers.insert("sec-ch-ua", HeaderValue::from_static(USER_AGENT));
+    headers.insert("dnt", HeaderValue::from_static("1"));
+    headers.insert("sec-ch-ua-mobile", HeaderValue::from_static("?0"));
+    headers.insert(
+        "accept",
+        HeaderValue::from_static("application/json, text/plain, */*"),
+    );
+    headers.insert(
+        "x-requested-with",
+        HeaderValue::from_static("XMLHttpRequest"),
+    );
+    headers.insert(
+        "x-api-key",
+        HeaderValue::from_str(&api_key).map_err(|e| Error::InvalidResponse(e.to_string()))?,
     );
+    headers.insert(
+        "sec-ch-ua-platform",
+        HeaderValue::from_static("\"Windows\""),
+    );
+    headers.insert("origin", HeaderValue::from_static("https://soutubot.moe"));
+    headers.insert("sec-fetch-site", HeaderValue::from_static("same-origin"));
+    headers.insert("sec-fetch-mode", HeaderValue::from_static("cors"));
+    headers.insert("sec-fetch-dest", HeaderValue::from_static("empty"));
+    headers.insert("referer", HeaderValue::from_static("https://soutubot.moe/"));
+    headers.insert(
+        "accept-language",
+        HeaderValue::from_static("zh-CN,zh;q=0.9"),
+    );
+    Ok(headers)
+}
 
-    // 发送请求
-    let response = client
-        .post("https://soutubot.moe/api/search")
-        .headers(headers)
-        .multipart(form)
-        .send()
-        .await?;
-
-    let status = response.status();
-    if !status.is_success() {
-        return Err(Error::InvalidResponse(format!("HTTP {}", status)).into());
+/// 结果所在站点的域名，e-hentai 的画廊同时可以通过 exhentai 访问
+fn hosts(source: &str) -> &'static [&'static str] {
+    if source == "nhentai" {
+        &["https://nhentai.net"]
+    } else {
+        &["https://e-hentai.org", "https://exhentai.org"]
     }
+}
 
-    let soutubot_response: SoutubotResponse = response.json().await?;
-    // json格式 {"data":[],"id":"2025020814015112","factor":1.4,"imageUrl":"https:\/\/img.76888268.xyz\/img\/8abba7d56ebab7885b2a68cf0d57c742.webp","searchOption":"api 1.4 Liner 64","executionTime":2.6}
-    // 结果页链接
-    let result_page_url = format!("https://soutubot.moe/results/{}", soutubot_response.id);
-
-    // 转换结果
-    let results = soutubot_response
-        .data
-        .into_iter()
-        .map(|result| {
-            let detail_url = if result.source == "nhentai" {
-                format!("https://www.{}.net{}", result.source, result.subject_path)
-            } else {
-                format!(
-                    "https://e-hentai.org{}\nhttps://exhentai.org{}",
-                    result.subject_path, result.subject_path
-                )
-            };
-
-            SearchResult {
-                title: Some(result.title),
-                url: detail_url,
-                thumbnail: Some(result.preview_image_url),
-                similarity: Some(result.similarity),
-                source: result.source,
-                index: None,
-                additional_info: None,
-            }
-        })
-        .collect();
+fn convert_result(result: SoutubotResult) -> SearchResult {
+    let hosts = hosts(&result.source);
+    let mut urls = hosts
+        .iter()
+        .map(|host| format!("{}{}", host, result.subject_path));
+    let url = urls.next().unwrap_or_default();
+
+    // 命中的具体页面，而不仅是画廊首页
+    let page_url = result
+        .page_path
+        .filter(|p| !p.is_empty())
+        .map(|page_path| format!("{}{}", hosts[0], page_path));
 
-    Ok((result_page_url, results))
+    // nhentai 只收录成人本子；e-hentai 的画廊分级不一，无法从结果中判断
+    let rating = (result.source == "nhentai").then_some(Rating::Explicit);
+
+    SearchResult {
+        title: Some(result.title),
+        url,
+        thumbnail: Some(result.preview_image_url),
+        similarity: Some(result.similarity),
+        source: result.source,
+        additional_info: Some(AdditionalInfo {
+            ext_urls: urls.collect(),
+            rating,
+            language: Some(result.language).filter(|l| !l.is_empty()),
+            page_url,
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
 }
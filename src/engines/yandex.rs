@@ -1,22 +1,71 @@
 use super::ImageSearch;
 use crate::error::{Error, Result};
-use crate::network::Network;
+use crate::network::{Form, Network};
+use crate::preprocess::{self, UploadConstraints};
 use crate::types::{AdditionalInfo, SearchOptions, SearchResult};
 use async_trait::async_trait;
 use base64::engine::general_purpose::STANDARD;
 use base64::Engine;
+use image::ImageFormat;
+use log::{debug, warn};
 use reqwest::header::{
-    HeaderMap, HeaderValue, ACCEPT, ACCEPT_LANGUAGE, CACHE_CONTROL, COOKIE, DNT, REFERER,
-    USER_AGENT,
+    HeaderMap, HeaderValue, ACCEPT, ACCEPT_LANGUAGE, CACHE_CONTROL, DNT, REFERER, USER_AGENT,
 };
-use reqwest::multipart;
+use reqwest_cookie_store::CookieStoreMutex;
 use scraper::{Html, Selector};
 use serde_json::Value;
+use std::collections::HashSet;
+use std::sync::Arc;
+use tokio::sync::OnceCell;
+use url::Url;
+
+const ENGINE: &str = "Yandex";
+
+/// 匿名会话的标识 cookie，访问主页时由 Yandex 下发
+const SESSION_COOKIE: &str = "yandexuid";
+
+/// 结果页类型：包含该图片的网站 / 该图片的其他尺寸
+const CBIR_PAGE_SITES: &str = "sites";
+const CBIR_PAGE_SIZES: &str = "sizes";
+
+/// Yandex 识别出的标签或相关搜索词
+#[derive(Debug, Clone)]
+pub struct YandexTag {
+    pub text: String,
+    pub url: Option<String>,
+}
+
+/// Yandex 识别出的物体、人物或地标
+#[derive(Debug, Clone)]
+pub struct YandexObject {
+    pub title: String,
+    pub subtitle: Option<String>,
+    pub description: Option<String>,
+    pub url: Option<String>,
+    pub thumbnail: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct YandexResponse {
+    pub url: String,
+    /// 包含该图片的网站，对应 `cbir_page=sites`
+    pub results: Vec<SearchResult>,
+    pub similar_images: Vec<SearchResult>,
+    pub tags: Vec<YandexTag>,
+    pub objects: Vec<YandexObject>,
+    /// 图片中识别出的文字
+    pub ocr_text: Option<String>,
+    /// 同一张图片的其他尺寸
+    pub other_sizes: Vec<SearchResult>,
+}
 
 #[derive(Debug)]
 pub struct Yandex {
     network: Network,
     base_url: String,
+    home_url: String,
+    cookies: Arc<CookieStoreMutex>,
+    session: OnceCell<()>,
 }
 
 impl Default for Yandex {
@@ -27,13 +76,92 @@ impl Default for Yandex {
 
 impl Yandex {
     pub fn new(base_url: &str) -> Self {
+        let cookies = Arc::new(CookieStoreMutex::default());
         Self {
-            network: Network::new(),
+            network: Network::with_cookie_store(Arc::clone(&cookies)).for_engine(ENGINE),
             base_url: format!("{}/images/search", base_url),
+            home_url: base_url.to_string(),
+            cookies,
+            session: OnceCell::new(),
+        }
+    }
+
+    /// 从环境变量读取配置：`YANDEX_BASE_URL` 指定站点，`YANDEX_COOKIE` 为可选的 cookie 字符串
+    pub fn from_env() -> Self {
+        let base_url =
+            std::env::var("YANDEX_BASE_URL").unwrap_or_else(|_| "https://yandex.com".to_string());
+        let yandex = Self::new(&base_url);
+        match std::env::var("YANDEX_COOKIE") {
+            Ok(cookies) if !cookies.trim().is_empty() => yandex.with_cookies(&cookies),
+            _ => yandex,
         }
     }
 
-    fn build_headers() -> HeaderMap {
+    /// 使用浏览器中复制的 cookie 字符串（`name=value; name2=value2`）
+    pub fn with_cookies(self, cookies: &str) -> Self {
+        if let Ok(url) = Url::parse(&self.home_url) {
+            let domain = url.host_str().unwrap_or_default().to_string();
+            let mut store = self.cookies.lock().unwrap_or_else(|e| e.into_inner());
+            for pair in cookies
+                .split(';')
+                .map(str::trim)
+                .filter(|p| p.contains('='))
+            {
+                let cookie = format!("{}; Domain={}; Path=/", pair, domain);
+                if let Err(e) = store.parse(&cookie, &url) {
+                    warn!("忽略无效的 Yandex cookie {}: {}", pair, e);
+                }
+            }
+        }
+        self
+    }
+
+    /// 使用调用方提供的 cookie 存储，可在多个实例之间共享会话
+    pub fn with_cookie_store(mut self, cookies: Arc<CookieStoreMutex>) -> Self {
+        self.network = Network::with_cookie_store(Arc::clone(&cookies)).for_engine(ENGINE);
+        self.cookies = cookies;
+        self.session = OnceCell::new();
+        self
+    }
+
+    pub fn cookie_store(&self) -> Arc<CookieStoreMutex> {
+        Arc::clone(&self.cookies)
+    }
+
+    fn has_session_cookie(&self) -> bool {
+        let Ok(url) = Url::parse(&self.home_url) else {
+            return false;
+        };
+        let store = self.cookies.lock().unwrap_or_else(|e| e.into_inner());
+        let found = store
+            .get_request_values(&url)
+            .any(|(name, _)| name == SESSION_COOKIE);
+        found
+    }
+
+    /// 没有会话 cookie 时先访问主页，获取匿名的 yandexuid
+    async fn ensure_session(&self) -> Result<()> {
+        self.session
+            .get_or_try_init(|| async {
+                if self.has_session_cookie() {
+                    return Ok(());
+                }
+
+                debug!("访问 Yandex 主页以获取匿名会话");
+                self.network
+                    .get_with_headers(&self.home_url, self.build_headers())
+                    .await?;
+                if !self.has_session_cookie() {
+                    warn!("Yandex 主页未下发 {}，继续以无会话方式搜索", SESSION_COOKIE);
+                }
+                Ok::<(), Error>(())
+            })
+            .await?;
+        Ok(())
+    }
+
+    /// Referer 指向当前站点主页，自定义 `base_url` 时与实际请求的站点一致
+    fn build_headers(&self) -> HeaderMap {
         let mut headers = HeaderMap::new();
         headers.insert(
             USER_AGENT,
@@ -41,7 +169,11 @@ impl Yandex {
                 "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/133.0.0.0 Safari/537.36",
             ),
         );
-        headers.insert(REFERER, HeaderValue::from_static("https://yandex.com"));
+        if let Ok(referer) =
+            HeaderValue::from_str(&format!("{}/", self.home_url.trim_end_matches('/')))
+        {
+            headers.insert(REFERER, referer);
+        }
         headers.insert(
             ACCEPT,
             HeaderValue::from_static(
@@ -55,130 +187,291 @@ impl Yandex {
         headers.insert(CACHE_CONTROL, HeaderValue::from_static("max-age=0"));
         headers.insert(DNT, HeaderValue::from_static("1"));
 
-        // 添加 Cookie
-        headers.insert(
-            COOKIE,
-            HeaderValue::from_static("is_gdpr=0; is_gdpr_b=CNfYHxD8pwIoAg==; yandex_login=luoxuebinfei; yandexuid=4469646701730833589; yashr=6420963171730833589; receive-cookie-deprecation=1; L=XRZKBXsBTEZ6V1hfCVtFXWV7T1RkRWR2IjMLLxcvKwVWJRIZ.1732387245.15960.360833.41b26fb256271d6bdf81822b8adc46d5; font_loaded=YSv1; bltsr=1; KIykI=1; my=YwA=; ys=udn.cDpsdW94dWViaW5mZWk=#wprid.1737530593637953-16302891694983095392-balancer-l7leveler-kubr-yp-klg-199-BAL; Session_id=3:1737619595.5.0.1732387245930:T8puwQ:1282.1.2:1|1354280184.0.2.0:3.3:1732387245|11:10188134.641159.Tb8kijOidztkex2hHPhF_TNHF2Y; sessar=1.1198.CiBdPpK-v4JFFpRRA9W6sg8W1axG5bs0WdCvvrO3yYFRjA.4cOAuee_Z0_mxTwoDMGq4bFodZSLLkAWolyPzUW0hUs; sessionid2=3:1737619595.5.0.1732387245930:T8puwQ:1282.1.2:1|1354280184.0.2.0:3.3:1732387245|11:10188134.641159.fakesign0000000000000000000; i=0W8pwEtmL2fNP4Bfo+45Ac+dWaP0jNp/od8VwK+z7PoweyG3wGuuglpaTBJVohp38yMgO5B+ppaFA2gl2lfZ9lRyzTM=; _yasc=ly1J5plgvtniKU7ua6LxbUfO7Jb9G9wdLEP+7gxsL0XmM2y8JSbHkkjS4s1nkZaZgpPCl2vDlI/WY8lE; bh=EkEiTm90KEE6QnJhbmQiO3Y9Ijk5IiwgIkdvb2dsZSBDaHJvbWUiO3Y9IjEzMyIsICJDaHJvbWl1bSI7dj0iMTMzIhoFIng4NiIiDyIxMzMuMC42OTQzLjUzIioCPzAyAiIiOgkiV2luZG93cyJCCCIxNS4wLjAiSgQiNjQiUlsiTm90KEE6QnJhbmQiO3Y9Ijk5LjAuMC4wIiwgIkdvb2dsZSBDaHJvbWUiO3Y9IjEzMy4wLjY5NDMuNTMiLCAiQ2hyb21pdW0iO3Y9IjEzMy4wLjY5NDMuNTMiWgI/MGDu+Zi9Bmoe3Mrh/wiS2KGxA5/P4eoD+/rw5w3r//32D6K4zocI; yp=2052890594.pcs.0#2047747245.udn.cDpsdW94dWViaW5mZWk=#1753387600.szm.1:1920x1080:1903x911#1744132360.atds.1#1740208523.csc.1"),
-        );
-
         headers
     }
 
-    fn parse_html(html: &str) -> Result<Vec<SearchResult>> {
-        // 检查维护信息
-        if html.contains("The service is under construction") {
-            return Err(Error::Engine("Yandex 图片搜索服务正在维护中".to_string()));
+    /// 解析结果页中各组件的 `data-state` JSON
+    pub fn parse_html(html: &str) -> Result<YandexResponse> {
+        let document = Html::parse_document(html);
+        let sites = Self::data_state(&document, "CbirSites_infinite");
+
+        Ok(YandexResponse {
+            results: sites.as_ref().map(Self::parse_sites).unwrap_or_default(),
+            similar_images: Self::data_state(&document, "CbirSimilar")
+                .map(|json| Self::parse_similar(&json))
+                .unwrap_or_default(),
+            tags: Self::data_state(&document, "CbirTags")
+                .map(|json| Self::parse_tags(&json))
+                .unwrap_or_default(),
+            objects: Self::data_state(&document, "CbirObject")
+                .map(|json| Self::parse_objects(&json))
+                .unwrap_or_default(),
+            ocr_text: Self::data_state(&document, "CbirOcr")
+                .or_else(|| Self::data_state(&document, "CbirTextRecognition"))
+                .and_then(|json| Self::parse_ocr(&json)),
+            other_sizes: Self::data_state(&document, "CbirOtherSizes")
+                .or_else(|| Self::data_state(&document, "CbirSizes"))
+                .or(sites)
+                .map(|json| Self::parse_sizes(&json))
+                .unwrap_or_default(),
+            ..Default::default()
+        })
+    }
+
+    /// 读取 `div.Root` 组件上的 `data-state` JSON，组件 id 以 `prefix` 开头
+    fn data_state(document: &Html, prefix: &str) -> Option<Value> {
+        let selector = Selector::parse(&format!("div.Root[id^='{}']", prefix)).ok()?;
+        document
+            .select(&selector)
+            .filter_map(|div| div.value().attr("data-state"))
+            .find_map(|state| serde_json::from_str::<Value>(state).ok())
+    }
+
+    /// 在 `keys` 中找到第一个存在的数组字段
+    fn find_array<'a>(json: &'a Value, keys: &[&str]) -> &'a [Value] {
+        keys.iter()
+            .find_map(|key| json.get(key).and_then(|v| v.as_array()))
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    fn str_field(json: &Value, key: &str) -> Option<String> {
+        json.get(key)
+            .and_then(|v| v.as_str())
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(ToOwned::to_owned)
+    }
+
+    fn image_size(json: &Value) -> Option<(u32, u32)> {
+        let width = json.get("width").and_then(|v| v.as_u64())?;
+        let height = json.get("height").and_then(|v| v.as_u64())?;
+        Some((width as u32, height as u32))
+    }
+
+    fn absolute_url(src: &str) -> String {
+        if src.starts_with("//") {
+            format!("https:{}", src)
+        } else {
+            src.to_string()
         }
+    }
 
-        let document = Html::parse_document(html);
+    fn thumbnail(json: &Value) -> Option<String> {
+        json.get("thumb")
+            .and_then(|v| v.get("url"))
+            .or_else(|| json.get("thumbUrl"))
+            .and_then(|v| v.as_str())
+            .map(Self::absolute_url)
+    }
+
+    fn parse_sites(json: &Value) -> Vec<SearchResult> {
         let mut results = Vec::new();
+        for (index, site) in Self::find_array(json, &["sites"]).iter().enumerate() {
+            // 基本信息
+            let url = site.get("url").and_then(|v| v.as_str()).unwrap_or_default();
+            let title = site
+                .get("title")
+                .and_then(|v| v.as_str())
+                .unwrap_or_default();
+            let domain = site
+                .get("domain")
+                .and_then(|v| v.as_str())
+                .unwrap_or_default();
+            let snippet = Self::str_field(site, "description");
 
-        // 匹配 JSON 数据
-        let data_div_selector = Selector::parse("div.Root[id^='CbirSites_infinite']").unwrap();
-        if let Some(data_div) = document.select(&data_div_selector).next() {
-            if let Some(data_state) = data_div.value().attr("data-state") {
-                if let Ok(json) = serde_json::from_str::<Value>(data_state) {
-                    if let Some(sites) = json.get("sites").and_then(|v| v.as_array()) {
-                        for (index, site) in sites.iter().enumerate() {
-                            // 基本信息
-                            let url = site.get("url").and_then(|v| v.as_str()).unwrap_or_default();
-                            let title = site
-                                .get("title")
-                                .and_then(|v| v.as_str())
-                                .unwrap_or_default();
-                            let domain = site
-                                .get("domain")
-                                .and_then(|v| v.as_str())
-                                .unwrap_or_default();
-                            let _content = site
-                                .get("description")
-                                .and_then(|v| v.as_str())
-                                .unwrap_or_default();
-
-                            // 缩略图
-                            let thumbnail = site
-                                .get("thumb")
-                                .and_then(|v| v.get("url"))
-                                .and_then(|v| v.as_str())
-                                .map(|src| {
-                                    if src.starts_with("//") {
-                                        format!("https:{}", src)
-                                    } else {
-                                        src.to_string()
-                                    }
-                                });
-
-                            // 尺寸信息
-                            let (size, size_str) = if let Some(img) = site.get("originalImage") {
-                                let width = img.get("width").and_then(|v| v.as_u64());
-                                let height = img.get("height").and_then(|v| v.as_u64());
-                                match (width, height) {
-                                    (Some(w), Some(h)) => {
-                                        (Some((w as u32, h as u32)), Some(format!("{}x{}", w, h)))
-                                    }
-                                    _ => (None, None),
-                                }
-                            } else {
-                                (None, None)
-                            };
-
-                            if !url.is_empty() {
-                                results.push(SearchResult {
-                                    title: Some(format!(
-                                        "[{}] {} - {}",
-                                        domain,
-                                        title,
-                                        size_str.unwrap_or_default()
-                                    )),
-                                    url: url.to_string(),
-                                    thumbnail,
-                                    similarity: None,
-                                    source: "Yandex".to_string(),
-                                    index: Some(index.to_string()),
-                                    additional_info: Some(AdditionalInfo {
-                                        author: None,
-                                        author_url: None,
-                                        source_url: Some(format!("https://{}", domain)),
-                                        created_at: None,
-                                        tags: Vec::new(),
-                                        size,
-                                        ext_urls: vec![],
-                                    }),
-                                });
-                            }
-                        }
-                    }
-                }
+            // 尺寸信息
+            let size = site.get("originalImage").and_then(Self::image_size);
+            let size_str = size.map(|(w, h)| format!("{}x{}", w, h));
+
+            if !url.is_empty() {
+                results.push(SearchResult {
+                    title: Some(format!(
+                        "[{}] {} - {}",
+                        domain,
+                        title,
+                        size_str.unwrap_or_default()
+                    )),
+                    url: url.to_string(),
+                    thumbnail: Self::thumbnail(site),
+                    source: "Yandex".to_string(),
+                    index: Some(index.to_string()),
+                    additional_info: Some(AdditionalInfo {
+                        author: None,
+                        author_url: None,
+                        // 原图地址，域名单独记录，避免同一站点的不同结果被当作同一作品
+                        source_url: site
+                            .get("originalImage")
+                            .and_then(|img| Self::str_field(img, "url")),
+                        site: Some(domain.to_string()).filter(|d| !d.is_empty()),
+                        created_at: None,
+                        tags: Vec::new(),
+                        size,
+                        snippet,
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                });
             }
         }
+        results
+    }
 
-        Ok(results)
+    fn parse_similar(json: &Value) -> Vec<SearchResult> {
+        Self::find_array(json, &["thumbs", "similar", "images", "items"])
+            .iter()
+            .enumerate()
+            .filter_map(|(index, item)| {
+                let page_url = Self::str_field(item, "url")
+                    .or_else(|| Self::str_field(item, "pageUrl"))
+                    .map(|url| Self::absolute_url(&url))?;
+                let image_url = Self::str_field(item, "imageUrl").or_else(|| {
+                    item.get("originalImage")
+                        .and_then(|v| Self::str_field(v, "url"))
+                });
+                let size = Self::image_size(item)
+                    .or_else(|| item.get("originalImage").and_then(Self::image_size));
+
+                Some(SearchResult {
+                    title: Self::str_field(item, "title"),
+                    url: page_url,
+                    thumbnail: Self::thumbnail(item),
+                    source: "Yandex".to_string(),
+                    index: Some(index.to_string()),
+                    additional_info: Some(AdditionalInfo {
+                        source_url: image_url,
+                        size,
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                })
+            })
+            .collect()
     }
 
-    async fn parse_results(&self, html: &str) -> Result<Vec<SearchResult>> {
-        let results = Self::parse_html(html)?;
-        Ok(results)
+    fn parse_tags(json: &Value) -> Vec<YandexTag> {
+        Self::find_array(json, &["tags", "items"])
+            .iter()
+            .filter_map(|tag| {
+                Some(YandexTag {
+                    text: Self::str_field(tag, "text").or_else(|| Self::str_field(tag, "title"))?,
+                    url: Self::str_field(tag, "url").map(|url| Self::absolute_url(&url)),
+                })
+            })
+            .collect()
     }
-}
 
-#[async_trait]
-impl ImageSearch for Yandex {
-    fn name(&self) -> &'static str {
-        "Yandex"
+    fn parse_objects(json: &Value) -> Vec<YandexObject> {
+        let parse = |item: &Value| {
+            Some(YandexObject {
+                title: Self::str_field(item, "title").or_else(|| Self::str_field(item, "name"))?,
+                subtitle: Self::str_field(item, "subtitle"),
+                description: Self::str_field(item, "description")
+                    .or_else(|| Self::str_field(item, "text")),
+                url: Self::str_field(item, "url").map(|url| Self::absolute_url(&url)),
+                thumbnail: Self::thumbnail(item),
+            })
+        };
+
+        // 既可能是实体列表，也可能是单个实体
+        let items = Self::find_array(json, &["objects", "entities", "items"]);
+        if items.is_empty() {
+            json.get("entity")
+                .or(Some(json))
+                .and_then(parse)
+                .into_iter()
+                .collect()
+        } else {
+            items.iter().filter_map(parse).collect()
+        }
     }
 
-    async fn search_url(
-        &self,
-        url: &str,
-        _options: &SearchOptions,
-    ) -> Result<(String, Vec<SearchResult>)> {
+    fn parse_ocr(json: &Value) -> Option<String> {
+        if let Some(text) = Self::str_field(json, "text") {
+            return Some(text);
+        }
+
+        let lines: Vec<String> = Self::find_array(json, &["blocks", "lines"])
+            .iter()
+            .filter_map(|block| Self::str_field(block, "text"))
+            .collect();
+        (!lines.is_empty()).then(|| lines.join("\n"))
+    }
+
+    fn parse_sizes(json: &Value) -> Vec<SearchResult> {
+        Self::find_array(json, &["sizes", "otherSizes"])
+            .iter()
+            .filter_map(|item| {
+                let url = Self::str_field(item, "url")
+                    .or_else(|| Self::str_field(item, "imageUrl"))
+                    .map(|url| Self::absolute_url(&url))?;
+                let size = Self::image_size(item);
+
+                Some(SearchResult {
+                    title: size.map(|(w, h)| format!("{}x{}", w, h)),
+                    url: url.clone(),
+                    source: "Yandex".to_string(),
+                    additional_info: Some(AdditionalInfo {
+                        source_url: Some(url),
+                        size,
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                })
+            })
+            .collect()
+    }
+
+    /// 通过图片 URL 搜索，返回包含相似图片、标签、识别文字等全部区块的结果
+    pub async fn search_url_page(&self, url: &str) -> Result<YandexResponse> {
+        self.fetch_url_page(url, CBIR_PAGE_SITES).await
+    }
+
+    /// 上传图片搜索，返回包含相似图片、标签、识别文字等全部区块的结果
+    pub async fn search_bytes_page(&self, bytes: &[u8]) -> Result<YandexResponse> {
+        self.fetch_bytes_page(bytes, CBIR_PAGE_SITES).await
+    }
+
+    /// 通过图片 URL 查询该图片的所有已知尺寸，按分辨率从高到低排序
+    pub async fn search_sizes_url(&self, url: &str) -> Result<(String, Vec<SearchResult>)> {
+        let response = self.fetch_url_page(url, CBIR_PAGE_SIZES).await?;
+        Ok((response.url, Self::sort_by_resolution(response.other_sizes)))
+    }
+
+    /// 上传图片查询该图片的所有已知尺寸，按分辨率从高到低排序
+    pub async fn search_sizes_bytes(&self, bytes: &[u8]) -> Result<(String, Vec<SearchResult>)> {
+        let response = self.fetch_bytes_page(bytes, CBIR_PAGE_SIZES).await?;
+        Ok((response.url, Self::sort_by_resolution(response.other_sizes)))
+    }
+
+    fn sort_by_resolution(mut sizes: Vec<SearchResult>) -> Vec<SearchResult> {
+        let pixels = |result: &SearchResult| {
+            result
+                .additional_info
+                .as_ref()
+                .and_then(|info| info.size)
+                .map_or(0, |(w, h)| w as u64 * h as u64)
+        };
+        // 同一链接可能以不同尺寸出现多次且不相邻，用 HashSet 去重；先排序，保留尺寸最大的一条
+        sizes.sort_by_key(|result| std::cmp::Reverse(pixels(result)));
+        let mut seen = HashSet::new();
+        sizes.retain(|result| seen.insert(result.url.clone()));
+        for (index, result) in sizes.iter_mut().enumerate() {
+            result.index = Some(index.to_string());
+        }
+        sizes
+    }
+
+    async fn fetch_url_page(&self, url: &str, cbir_page: &str) -> Result<YandexResponse> {
+        self.ensure_session().await?;
+
         // 构建请求头
-        let headers = Self::build_headers();
+        let headers = self.build_headers();
 
         // 构建请求 URL
         let search_url = format!(
-            "{}?rpt=imageview&url={}&cbir_page=sites",
-            self.base_url, url
+            "{}?rpt=imageview&url={}&cbir_page={}",
+            self.base_url, url, cbir_page
         );
 
         // 发送请求
@@ -186,52 +479,86 @@ impl ImageSearch for Yandex {
         let response_url = response.url().to_string();
 
         if !response.status().is_success() {
-            return Err(Error::Engine(format!(
-                "Yandex returned status code: {}",
-                response.status()
-            )));
+            return Err(Error::http_status(ENGINE, response).await);
         }
 
-        let html = response.text().await?;
-        let results = self.parse_results(&html).await?;
-
-        Ok((response_url, results))
+        let html = self.network.read_text(response).await?;
+        let mut response = Self::parse_html(&html)?;
+        response.url = response_url;
+        Ok(response)
     }
 
-    async fn search_bytes(
-        &self,
-        bytes: &[u8],
-        _options: &SearchOptions,
-    ) -> Result<(String, Vec<SearchResult>)> {
+    async fn fetch_bytes_page(&self, bytes: &[u8], cbir_page: &str) -> Result<YandexResponse> {
+        self.ensure_session().await?;
+
         // 构建请求头
-        let headers = Self::build_headers();
+        let headers = self.build_headers();
 
         // 构建 multipart 表单
-        let part = multipart::Part::bytes(bytes.to_vec())
-            .file_name("image.jpg")
-            .mime_str("image/jpeg")?;
+        let image = preprocess::prepare(bytes, &self.upload_constraints())
+            .map_err(|e| e.with_engine(self.name()))?;
+        let part = image.into_part();
 
-        let form = multipart::Form::new().text("prg", "1").part("upfile", part);
+        let form = Form::new().text("prg", "1").part("upfile", part);
 
         // 发送请求 - 参数放在 URL 中
-        let search_url = format!("{}?rpt=imageview&cbir_page=sites", self.base_url);
+        let search_url = format!("{}?rpt=imageview&cbir_page={}", self.base_url, cbir_page);
         let response = self
             .network
-            .post_multipart_with_headers(&search_url, form, headers)
+            .post_multipart_with_headers(&search_url, &form, headers)
             .await?;
         let response_url = response.url().to_string();
 
         if !response.status().is_success() {
-            return Err(Error::Engine(format!(
-                "上传失败: 状态码 {}",
-                response.status()
-            )));
+            return Err(Error::http_status(ENGINE, response).await);
+        }
+
+        let html = self.network.read_text(response).await?;
+        let mut response = Self::parse_html(&html)?;
+        response.url = response_url;
+        Ok(response)
+    }
+}
+
+#[async_trait]
+impl ImageSearch for Yandex {
+    fn name(&self) -> &'static str {
+        ENGINE
+    }
+
+    fn upload_constraints(&self) -> UploadConstraints {
+        UploadConstraints {
+            formats: &[
+                ImageFormat::Jpeg,
+                ImageFormat::Png,
+                ImageFormat::Gif,
+                ImageFormat::WebP,
+            ],
+            max_dimension: None,
+            max_bytes: Some(8 * 1024 * 1024),
         }
+    }
+
+    fn cache_key(&self) -> String {
+        self.base_url.clone()
+    }
 
-        let html = response.text().await?;
-        let results = self.parse_results(&html).await?;
+    async fn search_url(
+        &self,
+        url: &str,
+        _options: &SearchOptions,
+    ) -> Result<(String, Vec<SearchResult>)> {
+        let response = self.search_url_page(url).await?;
+        Ok((response.url, response.results))
+    }
 
-        Ok((response_url, results))
+    async fn search_bytes(
+        &self,
+        bytes: &[u8],
+        _options: &SearchOptions,
+    ) -> Result<(String, Vec<SearchResult>)> {
+        let response = self.search_bytes_page(bytes).await?;
+        Ok((response.url, response.results))
     }
 
     async fn search_file(
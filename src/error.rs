@@ -20,11 +20,63 @@ pub enum Error {
     #[error("Base64 decode error: {0}")]
     Base64(#[from] base64::DecodeError),
 
+    #[error("Unsupported image: {0}")]
+    UnsupportedImage(String),
+
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(String),
+
     #[error("Search engine error: {0}")]
     Engine(String),
 
-    #[error("Rate limit exceeded")]
-    RateLimit,
+    #[error("{engine} requires a captcha")]
+    Captcha { engine: &'static str },
+
+    #[error("{engine} blocked the request: {reason}")]
+    Blocked {
+        engine: &'static str,
+        reason: String,
+    },
+
+    #[error("{engine} returned HTTP {code}: {body_snippet}")]
+    HttpStatus {
+        engine: &'static str,
+        code: u16,
+        /// 响应正文的开头部分，便于排查
+        body_snippet: String,
+    },
+
+    #[error("{engine} failed to parse response: {stage}")]
+    Parse {
+        engine: &'static str,
+        /// 解析失败的环节
+        stage: String,
+    },
+
+    #[error("{engine} search quota exhausted")]
+    QuotaExhausted { engine: &'static str },
+
+    #[error("{engine} rejected the API key")]
+    InvalidApiKey { engine: &'static str },
+
+    #[error("{engine} does not support this request: {reason}")]
+    Unsupported {
+        engine: &'static str,
+        reason: String,
+    },
+
+    #[error("{engine} request timed out")]
+    Timeout { engine: &'static str },
+
+    #[error("{engine} API error {code}: {message}")]
+    Api {
+        engine: &'static str,
+        code: i32,
+        message: String,
+    },
+
+    #[error("{engine} rate limit exceeded")]
+    RateLimit { engine: &'static str },
 
     #[error("Invalid response: {0}")]
     InvalidResponse(String),
@@ -33,4 +85,80 @@ pub enum Error {
     UrlEncode(#[from] serde_urlencoded::ser::Error),
 }
 
+/// `HttpStatus` 中保留的正文长度（字符数）
+const BODY_SNIPPET_LEN: usize = 200;
+
+impl Error {
+    /// 由非成功状态的响应构造错误，读取正文开头作为摘要；拦截页面报告为验证码或封禁
+    pub async fn http_status(engine: &'static str, mut response: reqwest::Response) -> Self {
+        let _permit = crate::network::hold_permit(&mut response);
+        let code = response.status().as_u16();
+        let url = response.url().to_string();
+        let headers = response.headers().clone();
+        let body = response.text().await.unwrap_or_default();
+        if let Some(block) = crate::block::detect(&url, code, &headers, &body) {
+            return block.into_error(engine);
+        }
+        Error::HttpStatus {
+            engine,
+            code,
+            body_snippet: body.trim().chars().take(BODY_SNIPPET_LEN).collect(),
+        }
+    }
+
+    pub fn parse(engine: &'static str, stage: impl Into<String>) -> Self {
+        Error::Parse {
+            engine,
+            stage: stage.into(),
+        }
+    }
+
+    /// 为不带引擎信息的错误补上引擎，例如超时与图片格式不受支持
+    pub fn with_engine(self, engine: &'static str) -> Self {
+        match self {
+            Error::Request(e) if e.is_timeout() => Error::Timeout { engine },
+            Error::UnsupportedImage(reason) => Error::Unsupported { engine, reason },
+            other => other,
+        }
+    }
+
+    /// 产生错误的引擎，与 `ImageSearch::name()` 一致
+    pub fn engine(&self) -> Option<&'static str> {
+        match self {
+            Error::Api { engine, .. }
+            | Error::Captcha { engine }
+            | Error::Blocked { engine, .. }
+            | Error::HttpStatus { engine, .. }
+            | Error::Parse { engine, .. }
+            | Error::QuotaExhausted { engine }
+            | Error::InvalidApiKey { engine }
+            | Error::Unsupported { engine, .. }
+            | Error::Timeout { engine }
+            | Error::RateLimit { engine } => Some(engine),
+            _ => None,
+        }
+    }
+
+    /// 稍后重试是否可能成功：网络故障、超时、SauceNAO 用正数 status 报告的服务端临时故障，
+    /// 以及该引擎重试规则（`retry::policy`）中列出的 HTTP 状态码，限速视为 429；
+    /// 网络层与 `retry::retry` 都按这一规则决定是否重试
+    ///
+    /// 验证码、封禁、额度与 API key 问题需要人工处理，重试无益
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::Request(e) => crate::retry::is_transient(e),
+            Error::Timeout { .. } => true,
+            Error::HttpStatus { engine, code, .. } => {
+                crate::retry::policy(engine).retry_statuses.contains(code)
+            }
+            Error::RateLimit { engine } => {
+                crate::retry::policy(engine).retry_statuses.contains(&429)
+            }
+            // 只有 SauceNAO 约定正数 status 为临时故障，其他引擎的错误码不重试
+            Error::Api { engine, code, .. } => *engine == "SauceNAO" && *code > 0,
+            _ => false,
+        }
+    }
+}
+
 pub type Result<T> = std::result::Result<T, Error>;
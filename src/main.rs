@@ -1,118 +1,782 @@
-use anyhow::Result;
+use anyhow::{anyhow, bail, Result};
 use reverse_image_search::{
+    cache::{Cache, CachedSearch},
     engines::{Ascii2d, Google, GoogleLens, Iqdb, SauceNao, Soutubot, Yandex},
-    types::{SearchOptions, SearchResult},
+    merge,
+    network::{Network, MAX_DOWNLOAD_SIZE},
+    rank::{RankedResult, Ranker},
+    types::{SearchOptions, SearchResponse, SearchResult},
     ImageSearch,
 };
 use std::env;
-use std::path::Path;
-
-async fn test_engine<T: ImageSearch>(
-    engine: &T,
-    url: &str,
-    file_path: &str,
-    options: &SearchOptions,
-) -> Result<()> {
-    println!("\n测试 {} 引擎:", engine.name());
-
-    // URL 搜索
-    println!("\nURL 搜索结果:");
-    match engine.search_url(url, options).await {
-        Ok((page_url, results)) => {
-            if !page_url.is_empty() {
-                println!("结果页面: {}", page_url);
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+const USAGE_ZH: &str = "用法:
+  reverse-image-search search [选项] <图片>
+  reverse-image-search engines
+  reverse-image-search config [选项]
+
+<图片> 可以是本地路径、http(s) 链接、`-`（从标准输入读取）、data URI，
+或 `base64:` 开头的 base64 字符串
+
+选项:
+  -e, --engine <名称>         使用的引擎，可重复或用逗号分隔，默认使用全部引擎
+      --proxy <地址>          代理地址，例如 http://127.0.0.1:7890
+      --timeout <秒>          请求超时时间
+      --min-similarity <值>   最低相似度（0-100）
+      --hide-explicit         隐藏成人内容（SauceNAO、IQDB、Soutubot 支持）
+      --auto-crop             上传前裁掉纯色边框与黑边
+      --verify                下载结果缩略图，计算本地相似度
+      --cache <目录>          缓存搜索结果的目录
+      --merge                 合并各引擎指向同一作品的结果，按置信度排序输出
+      --lang <zh|en>          输出语言，默认根据 LANG 环境变量选择
+  -h, --help                  显示帮助
+
+环境变量:
+  SAUCENAO_API_KEY            SauceNAO 的 API key
+  YANDEX_BASE_URL             Yandex 站点，默认 https://yandex.com
+  YANDEX_COOKIE               Yandex 的 cookie 字符串";
+
+const USAGE_EN: &str = "Usage:
+  reverse-image-search search [options] <image>
+  reverse-image-search engines
+  reverse-image-search config [options]
+
+<image> can be a local path, an http(s) link, `-` (read from stdin), a data URI,
+or a base64 string prefixed with `base64:`
+
+Options:
+  -e, --engine <name>         Engines to use, repeatable or comma-separated; all engines by default
+      --proxy <url>           Proxy, e.g. http://127.0.0.1:7890
+      --timeout <seconds>     Request timeout
+      --min-similarity <n>    Minimum similarity (0-100)
+      --hide-explicit         Hide adult content (honoured by SauceNAO, IQDB and Soutubot)
+      --auto-crop             Crop solid borders and letterboxing before uploading
+      --verify                Download result thumbnails and compute local similarity
+      --cache <dir>           Directory for caching search results
+      --merge                 Merge results pointing to the same work and rank them by confidence
+      --lang <zh|en>          Output language; chosen from the LANG environment variable by default
+  -h, --help                  Show this help
+
+Environment variables:
+  SAUCENAO_API_KEY            SauceNAO API key
+  YANDEX_BASE_URL             Yandex site, https://yandex.com by default
+  YANDEX_COOKIE               Yandex cookie string";
+
+/// 命令行输出的语言
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Lang {
+    Zh,
+    En,
+}
+
+impl Lang {
+    /// 依次查看 LC_ALL、LC_MESSAGES、LANG，中文环境或未设置时使用中文，其他使用英文
+    fn from_env() -> Self {
+        let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .find_map(|name| env::var(name).ok().filter(|value| !value.is_empty()));
+        match locale {
+            Some(locale) if !locale.to_lowercase().starts_with("zh") => Lang::En,
+            _ => Lang::Zh,
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "zh" | "zh-cn" | "cn" => Some(Lang::Zh),
+            "en" | "en-us" => Some(Lang::En),
+            _ => None,
+        }
+    }
+
+    fn t(self, zh: &'static str, en: &'static str) -> &'static str {
+        match self {
+            Lang::Zh => zh,
+            Lang::En => en,
+        }
+    }
+
+    fn usage(self) -> &'static str {
+        self.t(USAGE_ZH, USAGE_EN)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Command {
+    Search { input: String },
+    Engines,
+    Config,
+    Help,
+}
+
+#[derive(Debug)]
+struct Args {
+    command: Command,
+    engines: Vec<String>,
+    options: SearchOptions,
+    cache: Option<PathBuf>,
+    merge: bool,
+    lang: Lang,
+}
+
+/// 待搜索的图片
+#[derive(Debug, PartialEq)]
+enum Input {
+    Url(String),
+    Bytes(Vec<u8>),
+}
+
+/// `--name value` 或 `--name=value` 形式的 `--lang`，先于其他参数确定，错误信息也使用该语言
+fn find_lang(args: &[String]) -> Option<String> {
+    args.iter()
+        .enumerate()
+        .find_map(|(i, arg)| match arg.split_once('=') {
+            Some(("--lang", value)) => Some(value.to_string()),
+            _ if arg == "--lang" => args.get(i + 1).cloned(),
+            _ => None,
+        })
+}
+
+fn parse_args(args: impl IntoIterator<Item = String>, default_lang: Lang) -> Result<Args> {
+    let args: Vec<String> = args.into_iter().collect();
+    let lang = match find_lang(&args) {
+        Some(value) => Lang::parse(&value).ok_or_else(|| {
+            anyhow!(
+                "{}: {}",
+                default_lang.t(
+                    "无效的语言，可选 zh 或 en",
+                    "invalid language, expected zh or en"
+                ),
+                value
+            )
+        })?,
+        None => default_lang,
+    };
+
+    let mut args = args.into_iter();
+    let mut command = None;
+    let mut positional = Vec::new();
+    let mut engines = Vec::new();
+    let mut options = SearchOptions::default();
+    let mut cache = None;
+    let mut merge = false;
+
+    while let Some(arg) = args.next() {
+        // 同时支持 `--name value` 与 `--name=value`
+        let (name, inline) = match arg.split_once('=') {
+            Some((name, value)) if name.starts_with("--") => {
+                (name.to_string(), Some(value.to_string()))
+            }
+            _ => (arg.clone(), None),
+        };
+        let mut value = || {
+            inline.clone().or_else(|| args.next()).ok_or_else(|| {
+                anyhow!(
+                    "{} {}",
+                    lang.t("缺少参数的选项:", "missing value for option"),
+                    name
+                )
+            })
+        };
+
+        match name.as_str() {
+            "-h" | "--help" => command = Some(Command::Help),
+            "-e" | "--engine" => engines.extend(
+                value()?
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|name| !name.is_empty())
+                    .map(str::to_string),
+            ),
+            "--proxy" => options.proxy = Some(value()?),
+            "--timeout" => {
+                let timeout = value()?;
+                options.timeout = Some(timeout.parse().map_err(|_| {
+                    anyhow!(
+                        "{}: {}",
+                        lang.t("无效的超时时间", "invalid timeout"),
+                        timeout
+                    )
+                })?);
+            }
+            "--min-similarity" => {
+                let similarity = value()?;
+                options.min_similarity = Some(similarity.parse().map_err(|_| {
+                    anyhow!(
+                        "{}: {}",
+                        lang.t("无效的相似度", "invalid similarity"),
+                        similarity
+                    )
+                })?);
+            }
+            "--hide-explicit" => options.hide_explicit = true,
+            "--auto-crop" => options.auto_crop = true,
+            "--verify" => options.verify = true,
+            "--cache" => cache = Some(PathBuf::from(value()?)),
+            "--merge" => merge = true,
+            // 已经在 find_lang 中处理
+            "--lang" => {
+                value()?;
             }
-            print_results(&results);
+            // 单独的 `-` 表示标准输入，不是选项
+            _ if name.starts_with('-') && name != "-" => bail!(
+                "{}: {}\n\n{}",
+                lang.t("未知选项", "unknown option"),
+                name,
+                lang.usage()
+            ),
+            _ if command.is_none() => {
+                command = Some(match arg.as_str() {
+                    "search" => Command::Search {
+                        input: String::new(),
+                    },
+                    "engines" => Command::Engines,
+                    "config" => Command::Config,
+                    _ => bail!(
+                        "{}: {}\n\n{}",
+                        lang.t("未知命令", "unknown command"),
+                        arg,
+                        lang.usage()
+                    ),
+                })
+            }
+            _ => positional.push(arg),
         }
-        Err(e) => println!("URL 搜索错误: {}", e),
     }
 
-    // 文件搜索
-    println!("\n文件搜索结果:");
-    match engine.search_file(file_path, options).await {
-        Ok((page_url, results)) => {
-            if !page_url.is_empty() {
-                println!("结果页面: {}", page_url);
+    let command = match command {
+        Some(Command::Search { .. }) => match positional.as_slice() {
+            [input] => Command::Search {
+                input: input.clone(),
+            },
+            [] => bail!(
+                "{}\n\n{}",
+                lang.t("缺少要搜索的图片", "missing the image to search"),
+                lang.usage()
+            ),
+            _ => bail!(
+                "{}",
+                lang.t(
+                    "一次只能搜索一张图片",
+                    "only one image can be searched at a time"
+                )
+            ),
+        },
+        Some(command) if positional.is_empty() => command,
+        Some(_) => bail!(
+            "{}: {}",
+            lang.t("多余的参数", "unexpected arguments"),
+            positional.join(" ")
+        ),
+        None => Command::Help,
+    };
+
+    Ok(Args {
+        command,
+        engines,
+        options,
+        cache,
+        merge,
+        lang,
+    })
+}
+
+/// 判断输入的类型并读取图片内容，链接交给引擎自行处理
+///
+/// 不带 `base64:` 前缀的字符串只有解码后能识别为图片时才视为 base64，避免把写错的路径当作图片上传
+fn read_input(input: &str, lang: Lang) -> Result<Input> {
+    let decode = |base64: &str| {
+        reverse_image_search::utils::base64_to_bytes(base64.trim()).map_err(|e| {
+            anyhow!(
+                "{}: {}",
+                lang.t("base64 解码失败", "failed to decode base64"),
+                e
+            )
+        })
+    };
+
+    if input == "-" {
+        let mut bytes = Vec::new();
+        std::io::stdin().read_to_end(&mut bytes).map_err(|e| {
+            anyhow!(
+                "{}: {}",
+                lang.t("读取标准输入失败", "failed to read stdin"),
+                e
+            )
+        })?;
+        return Ok(Input::Bytes(bytes));
+    }
+    if input.starts_with("http://") || input.starts_with("https://") {
+        return Ok(Input::Url(input.to_string()));
+    }
+    if let Some(data) = input.strip_prefix("data:") {
+        let (_, base64) = data.split_once(";base64,").ok_or_else(|| {
+            anyhow!(
+                "{}",
+                lang.t(
+                    "只支持 base64 编码的 data URI",
+                    "only base64-encoded data URIs are supported"
+                )
+            )
+        })?;
+        return Ok(Input::Bytes(decode(base64)?));
+    }
+    if let Some(base64) = input.strip_prefix("base64:") {
+        return Ok(Input::Bytes(decode(base64)?));
+    }
+    if Path::new(input).exists() {
+        return Ok(Input::Bytes(std::fs::read(input).map_err(|e| {
+            anyhow!(
+                "{} {}: {}",
+                lang.t("读取文件失败:", "failed to read file"),
+                input,
+                e
+            )
+        })?));
+    }
+    match reverse_image_search::utils::base64_to_bytes(input.trim()) {
+        Ok(bytes) if image::guess_format(&bytes).is_ok() => Ok(Input::Bytes(bytes)),
+        _ => bail!(
+            "{}: {}",
+            lang.t(
+                "找不到文件（base64 字符串请加上 base64: 前缀）",
+                "file not found (prefix base64 strings with base64:)"
+            ),
+            input
+        ),
+    }
+}
+
+/// 忽略大小写、空格与连字符比较引擎名，`lens` 为 Google Lens 的简写
+fn normalize(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect();
+    match name.as_str() {
+        "lens" => "googlelens".to_string(),
+        _ => name,
+    }
+}
+
+/// 指定了缓存目录时套上 `CachedSearch`
+fn boxed<S: ImageSearch + 'static>(engine: S, cache: Option<&Cache>) -> Box<dyn ImageSearch> {
+    match cache {
+        Some(cache) => Box::new(CachedSearch::new(engine, cache.clone())),
+        None => Box::new(engine),
+    }
+}
+
+fn all_engines(cache: Option<&Cache>) -> Vec<Box<dyn ImageSearch>> {
+    vec![
+        boxed(SauceNao::new(env::var("SAUCENAO_API_KEY").ok()), cache),
+        boxed(Ascii2d::new(), cache),
+        boxed(Google::new(), cache),
+        boxed(GoogleLens::new(), cache),
+        boxed(Iqdb::new(), cache),
+        boxed(Yandex::from_env(), cache),
+        boxed(Soutubot::new(), cache),
+    ]
+}
+
+fn select_engines(
+    names: &[String],
+    cache: Option<&Cache>,
+    lang: Lang,
+) -> Result<Vec<Box<dyn ImageSearch>>> {
+    let engines = all_engines(cache);
+    if names.is_empty() {
+        return Ok(engines);
+    }
+
+    let wanted: Vec<String> = names.iter().map(|name| normalize(name)).collect();
+    if let Some(unknown) = names
+        .iter()
+        .zip(&wanted)
+        .find(|(_, key)| !engines.iter().any(|e| normalize(e.name()) == **key))
+    {
+        bail!(
+            "{}: {}, {}: {}",
+            lang.t("未知引擎", "unknown engine"),
+            unknown.0,
+            lang.t("可用的引擎", "available engines"),
+            engines
+                .iter()
+                .map(|e| e.name())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+    Ok(engines
+        .into_iter()
+        .filter(|e| wanted.contains(&normalize(e.name())))
+        .collect())
+}
+
+async fn search(args: &Args, input: &str) -> Result<()> {
+    let (options, lang) = (&args.options, args.lang);
+    let cache = args.cache.as_ref().map(Cache::new);
+    let engines = select_engines(&args.engines, cache.as_ref(), lang)?;
+    let mut input = read_input(input, lang)?;
+
+    // 裁边与校验需要查询图片本身，先下载一次，各引擎改为上传
+    if let Input::Url(url) = &input {
+        if options.auto_crop || options.verify {
+            let bytes = Network::from_options(options)?
+                .download(url, MAX_DOWNLOAD_SIZE)
+                .await?;
+            input = Input::Bytes(bytes.to_vec());
+        }
+    }
+
+    // 各引擎的限速互相独立，同时发起搜索
+    let searches = engines.iter().map(|engine| async {
+        match &input {
+            Input::Url(url) => {
+                let (page_url, results) = engine.search_url(url, options).await?;
+                Ok(SearchResponse {
+                    page_url,
+                    results,
+                    crop: None,
+                })
+            }
+            Input::Bytes(bytes) => engine.search_image(bytes, options).await,
+        }
+    });
+    let outcomes: Vec<reverse_image_search::Result<SearchResponse>> =
+        futures::future::join_all(searches).await;
+
+    let mut failed = 0;
+    let mut batches = Vec::new();
+    for (engine, outcome) in engines.iter().zip(outcomes) {
+        match outcome {
+            Ok(response) => {
+                if !args.merge {
+                    println!("\n== {} ==", engine.name());
+                    print_response(&response, lang);
+                }
+                batches.push((engine.name(), response.results));
+            }
+            Err(e) => {
+                eprintln!(
+                    "{} {}: {}",
+                    engine.name(),
+                    lang.t("搜索失败", "search failed"),
+                    e
+                );
+                failed += 1;
             }
-            print_results(&results);
         }
-        Err(e) => println!("文件搜索错误: {}", e),
     }
 
+    if failed == engines.len() {
+        bail!("{}", lang.t("所有引擎都搜索失败", "all engines failed"));
+    }
+    if args.merge {
+        let ranking = Ranker::new().rank(merge::merge(batches));
+        print_ranking(ranking.best.iter().chain(&ranking.others), lang);
+    }
     Ok(())
 }
 
-fn print_results(results: &[SearchResult]) {
+fn print_response(response: &SearchResponse, lang: Lang) {
+    if !response.page_url.is_empty() {
+        println!(
+            "{}: {}",
+            lang.t("结果页面", "Results page"),
+            response.page_url
+        );
+    }
+    if let Some(crop) = &response.crop {
+        println!(
+            "{}: {}x{}+{}+{}",
+            lang.t("裁剪区域", "Cropped to"),
+            crop.width,
+            crop.height,
+            crop.x,
+            crop.y
+        );
+    }
+    print_results(&response.results, lang);
+}
+
+fn print_results(results: &[SearchResult], lang: Lang) {
     if results.is_empty() {
-        println!("未找到结果");
+        println!("{}", lang.t("未找到结果", "No results"));
         return;
     }
 
     for (i, result) in results.iter().enumerate() {
-        println!("\n结果 #{}:", i + 1);
-        println!("标题: {}", result.title.as_deref().unwrap_or("未知"));
+        println!("\n{} #{}:", lang.t("结果", "Result"), i + 1);
+        println!(
+            "{}: {}",
+            lang.t("标题", "Title"),
+            result.title.as_deref().unwrap_or(lang.t("未知", "unknown"))
+        );
         println!("URL: {}", result.url);
-        println!("缩略图: {}", result.thumbnail.as_deref().unwrap_or("无"));
+        println!(
+            "{}: {}",
+            lang.t("缩略图", "Thumbnail"),
+            result.thumbnail.as_deref().unwrap_or(lang.t("无", "none"))
+        );
         if let Some(similarity) = result.similarity {
-            println!("相似度: {}%", similarity);
+            println!("{}: {}%", lang.t("相似度", "Similarity"), similarity);
         }
-        println!("来源: {}", result.source);
+        if let Some(similarity) = result.local_similarity {
+            println!(
+                "{}: {:.1}%",
+                lang.t("本地相似度", "Local similarity"),
+                similarity * 100.0
+            );
+        }
+        println!("{}: {}", lang.t("来源", "Source"), result.source);
         if let Some(index) = &result.index {
-            println!("索引: {}", index);
+            println!("{}: {}", lang.t("索引", "Index"), index);
         }
         if let Some(info) = &result.additional_info {
             if let Some(author) = &info.author {
-                println!("作者: {}", author);
+                println!("{}: {}", lang.t("作者", "Author"), author);
             }
             if let Some(author_url) = &info.author_url {
-                println!("作者链接: {}", author_url);
+                println!("{}: {}", lang.t("作者链接", "Author URL"), author_url);
             }
             if !info.tags.is_empty() {
-                println!("标签: {}", info.tags.join(", "));
+                println!("{}: {}", lang.t("标签", "Tags"), info.tags.join(", "));
             }
         }
     }
 }
 
+fn print_ranking<'a>(ranked: impl Iterator<Item = &'a RankedResult>, lang: Lang) {
+    let mut empty = true;
+    for (i, ranked) in ranked.enumerate() {
+        empty = false;
+        let result = &ranked.result;
+        println!(
+            "\n#{} {}: {:.0}%",
+            i + 1,
+            lang.t("置信度", "Confidence"),
+            ranked.confidence * 100.0
+        );
+        println!("URL: {}", result.url);
+        if let Some(title) = &result.title {
+            println!("{}: {}", lang.t("标题", "Title"), title);
+        }
+        if let Some(source) = &result.source {
+            println!("{}: {}", lang.t("作品", "Work"), source);
+        }
+        println!(
+            "{}: {}",
+            lang.t("引擎", "Engines"),
+            result.engines.join(", ")
+        );
+        for line in &ranked.explanation {
+            println!("  - {}", line);
+        }
+    }
+    if empty {
+        println!("{}", lang.t("未找到结果", "No results"));
+    }
+}
+
+fn print_engines() {
+    for engine in all_engines(None) {
+        println!("{}", engine.name());
+    }
+}
+
+/// 显示命令行选项与环境变量合并后的实际配置
+fn print_config(args: &Args) {
+    let (options, lang) = (&args.options, args.lang);
+    let yes_no = |value: bool| {
+        if value {
+            lang.t("是", "yes")
+        } else {
+            lang.t("否", "no")
+        }
+    };
+    let is_set = |name: &str| match env::var(name) {
+        Ok(value) if !value.trim().is_empty() => lang.t("已设置", "set"),
+        _ => lang.t("未设置", "not set"),
+    };
+    let none = lang.t("无", "none");
+
+    println!(
+        "{}: {}",
+        lang.t("代理", "Proxy"),
+        options.proxy.as_deref().unwrap_or(none)
+    );
+    match options.timeout {
+        Some(timeout) => println!(
+            "{}: {} {}",
+            lang.t("超时", "Timeout"),
+            timeout,
+            lang.t("秒", "s")
+        ),
+        None => println!(
+            "{}: {}",
+            lang.t("超时", "Timeout"),
+            lang.t("默认", "default")
+        ),
+    }
+    match options.min_similarity {
+        Some(similarity) => println!(
+            "{}: {}",
+            lang.t("最低相似度", "Minimum similarity"),
+            similarity
+        ),
+        None => println!("{}: {}", lang.t("最低相似度", "Minimum similarity"), none),
+    }
+    println!(
+        "{}: {}",
+        lang.t("隐藏成人内容", "Hide explicit"),
+        yes_no(options.hide_explicit)
+    );
+    println!(
+        "{}: {}",
+        lang.t("自动裁边", "Auto crop"),
+        yes_no(options.auto_crop)
+    );
+    println!(
+        "{}: {}",
+        lang.t("本地校验", "Verify"),
+        yes_no(options.verify)
+    );
+    println!(
+        "{}: {}",
+        lang.t("缓存目录", "Cache directory"),
+        args.cache
+            .as_ref()
+            .map_or(none.to_string(), |dir| dir.display().to_string())
+    );
+    println!(
+        "{}: {}",
+        lang.t("合并结果", "Merge results"),
+        yes_no(args.merge)
+    );
+    println!("SAUCENAO_API_KEY: {}", is_set("SAUCENAO_API_KEY"));
+    println!(
+        "YANDEX_BASE_URL: {}",
+        env::var("YANDEX_BASE_URL").unwrap_or_else(|_| "https://yandex.com".to_string())
+    );
+    println!("YANDEX_COOKIE: {}", is_set("YANDEX_COOKIE"));
+}
+
+async fn run(lang: Lang) -> Result<()> {
+    let args = parse_args(env::args().skip(1), lang)?;
+    match &args.command {
+        Command::Search { input } => search(&args, input).await,
+        Command::Engines => {
+            print_engines();
+            Ok(())
+        }
+        Command::Config => {
+            print_config(&args);
+            Ok(())
+        }
+        Command::Help => {
+            println!("{}", args.lang.usage());
+            Ok(())
+        }
+    }
+}
+
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() {
     // 加载环境变量
     dotenv::dotenv().ok();
 
     // 初始化日志
     env_logger::init();
 
-    // 获取 API key
-    let api_key = env::var("SAUCENAO_API_KEY").ok();
-
-    // 创建搜索实例
-    let saucenao = SauceNao::new(api_key);
-    let ascii2d = Ascii2d::new();
-    let google = Google::new();
-    let google_lens = GoogleLens::new();
-    let iqdb = Iqdb::new();
-    let yandex = Yandex::default();
-    let options = SearchOptions::default();
-
-    let client = reqwest::Client::new();
-
-    // 初始化搜索引擎
-    let soutubot = Soutubot::new(client);
-
-    // 图片路径
-    let img_path = Path::new("test_img/2.png");
-    // 图片链接搜索
-    let url =
-        "https://telegraph-image-92x.pages.dev/file/bc7e6e4cc62f37b159357-6ad3189e5bc5d25dee.png";
-
-    // 测试每个引擎
-    test_engine(&saucenao, url, img_path.to_str().unwrap(), &options).await?;
-    test_engine(&ascii2d, url, img_path.to_str().unwrap(), &options).await?;
-    test_engine(&google, url, img_path.to_str().unwrap(), &options).await?;
-    test_engine(&google_lens, url, img_path.to_str().unwrap(), &options).await?;
-    test_engine(&iqdb, url, img_path.to_str().unwrap(), &options).await?;
-    test_engine(&yandex, url, img_path.to_str().unwrap(), &options).await?;
-    test_engine(&soutubot, url, img_path.to_str().unwrap(), &options).await?;
+    // 错误前缀按环境变量选择；`--lang` 只影响参数解析之后的输出
+    let lang = Lang::from_env();
+    if let Err(e) = run(lang).await {
+        eprintln!("{}: {}", lang.t("错误", "error"), e);
+        std::process::exit(1);
+    }
+}
 
-    Ok(())
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args> {
+        parse_args(args.iter().map(|arg| arg.to_string()), Lang::Zh)
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let args = parse(&[
+            "search",
+            "-e",
+            "saucenao,Google Lens",
+            "--engine=iqdb",
+            "--timeout",
+            "10",
+            "--min-similarity=80",
+            "--hide-explicit",
+            "--auto-crop",
+            "--verify",
+            "--cache",
+            "/tmp/ris",
+            "--merge",
+            "--lang=en",
+            "-",
+        ])
+        .unwrap();
+        assert_eq!(
+            args.command,
+            Command::Search {
+                input: "-".to_string()
+            }
+        );
+        assert_eq!(args.engines, ["saucenao", "Google Lens", "iqdb"]);
+        assert_eq!(args.options.timeout, Some(10));
+        assert_eq!(args.options.min_similarity, Some(80.0));
+        assert!(args.options.hide_explicit);
+        assert!(args.options.auto_crop && args.options.verify);
+        assert_eq!(args.cache, Some(PathBuf::from("/tmp/ris")));
+        assert!(args.merge);
+        assert_eq!(args.lang, Lang::En);
+
+        assert_eq!(parse(&[]).unwrap().command, Command::Help);
+        assert!(parse(&["search"]).is_err());
+        assert!(parse(&["engines", "extra"]).is_err());
+        assert!(parse(&["search", "--timeout", "soon", "a.png"]).is_err());
+        assert!(parse(&["engines", "--lang", "fr"]).is_err());
+        let error = parse(&["search", "--lang", "en"]).unwrap_err();
+        assert!(error.to_string().starts_with("missing the image"));
+
+        let names = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+        assert!(select_engines(&names(&["lens", "ascii-2d"]), None, Lang::Zh).is_ok());
+        assert!(select_engines(&names(&["tineye"]), None, Lang::Zh).is_err());
+    }
+
+    #[test]
+    fn test_read_input() {
+        assert_eq!(
+            read_input("data:image/png;base64,iVBORw0K", Lang::Zh).unwrap(),
+            Input::Bytes(vec![0x89, b'P', b'N', b'G', 0x0d, 0x0a])
+        );
+        assert_eq!(
+            read_input("https://example.com/a.png", Lang::Zh).unwrap(),
+            Input::Url("https://example.com/a.png".to_string())
+        );
+        assert_eq!(
+            read_input("base64:aGVsbG8=", Lang::Zh).unwrap(),
+            Input::Bytes(b"hello".to_vec())
+        );
+
+        // 不带前缀时只接受解码后能识别为图片的 base64
+        assert_eq!(
+            read_input("iVBORw0KGgo=", Lang::Zh).unwrap(),
+            Input::Bytes(b"\x89PNG\r\n\x1a\n".to_vec())
+        );
+        assert!(read_input("aGVsbG8=", Lang::Zh).is_err());
+        assert!(read_input("missing.png", Lang::Zh).is_err());
+    }
+}
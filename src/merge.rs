@@ -0,0 +1,301 @@
+use crate::canonical::{self, SourceRef};
+use crate::types::{AdditionalInfo, SearchResult};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// 某个引擎返回的一条原始结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EngineHit {
+    pub engine: String,
+    pub result: SearchResult,
+}
+
+/// 多个引擎指向同一作品的结果合并后的条目
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MergedResult {
+    /// 相似度最高的那条结果的链接
+    pub url: String,
+    /// 识别出的站点与作品 id
+    pub source: Option<SourceRef>,
+    pub title: Option<String>,
+    pub thumbnail: Option<String>,
+    /// 各引擎相似度中的最大值
+    pub similarity: Option<f32>,
+    pub local_similarity: Option<f32>,
+    /// 找到该作品的引擎，按首次出现的顺序
+    pub engines: Vec<String>,
+    /// 所有结果的附加信息取并集
+    pub additional_info: AdditionalInfo,
+    pub hits: Vec<EngineHit>,
+}
+
+/// 合并多个引擎的结果：链接规范化后相同（包括 `ext_urls` 与 `source_url`）的结果视为同一作品
+///
+/// 返回按最高相似度、引擎数量排序的条目
+pub fn merge<'a, I>(batches: I) -> Vec<MergedResult>
+where
+    I: IntoIterator<Item = (&'a str, Vec<SearchResult>)>,
+{
+    let hits: Vec<EngineHit> = batches
+        .into_iter()
+        .flat_map(|(engine, results)| {
+            results.into_iter().map(move |result| EngineHit {
+                engine: engine.to_string(),
+                result,
+            })
+        })
+        .collect();
+
+    // 并查集：共享任意一个规范化链接的结果归为一组
+    let mut parent: Vec<usize> = (0..hits.len()).collect();
+    let mut owner: HashMap<String, usize> = HashMap::new();
+    for (i, hit) in hits.iter().enumerate() {
+        for key in keys(&hit.result) {
+            match owner.get(&key) {
+                Some(&j) => union(&mut parent, i, j),
+                None => {
+                    owner.insert(key, i);
+                }
+            }
+        }
+    }
+
+    let mut groups: Vec<Vec<EngineHit>> = Vec::new();
+    let mut group_of: HashMap<usize, usize> = HashMap::new();
+    for (i, hit) in hits.into_iter().enumerate() {
+        let root = find(&mut parent, i);
+        let group = *group_of.entry(root).or_insert_with(|| {
+            groups.push(Vec::new());
+            groups.len() - 1
+        });
+        groups[group].push(hit);
+    }
+
+    let mut merged: Vec<MergedResult> = groups.into_iter().map(merge_group).collect();
+    merged.sort_by(|a, b| {
+        b.similarity
+            .unwrap_or(0.0)
+            .total_cmp(&a.similarity.unwrap_or(0.0))
+            .then(b.engines.len().cmp(&a.engines.len()))
+    });
+    merged
+}
+
+fn urls(result: &SearchResult) -> impl Iterator<Item = &str> {
+    let info = result.additional_info.as_ref();
+    std::iter::once(result.url.as_str())
+        .chain(info.and_then(|info| info.source_url.as_deref()))
+        .chain(
+            info.into_iter()
+                .flat_map(|info| info.ext_urls.iter().map(String::as_str)),
+        )
+}
+
+/// 只有域名的链接（例如站点首页）不能代表某个作品，不参与分组
+fn keys(result: &SearchResult) -> Vec<String> {
+    urls(result)
+        .filter(|url| !is_host_only(url))
+        .filter_map(canonical::canonicalize)
+        .collect()
+}
+
+fn is_host_only(url: &str) -> bool {
+    SourceRef::parse(url).is_none()
+        && canonical::parse_url(url).is_some_and(|parsed| {
+            parsed.path().trim_matches('/').is_empty() && parsed.query().is_none()
+        })
+}
+
+fn find(parent: &mut [usize], i: usize) -> usize {
+    let mut root = i;
+    while parent[root] != root {
+        root = parent[root];
+    }
+    parent[i] = root;
+    root
+}
+
+fn union(parent: &mut [usize], a: usize, b: usize) {
+    let (a, b) = (find(parent, a), find(parent, b));
+    if a != b {
+        parent[a.max(b)] = a.min(b);
+    }
+}
+
+fn merge_group(mut hits: Vec<EngineHit>) -> MergedResult {
+    // 相似度高的结果优先提供标题、作者等单值字段
+    hits.sort_by(|a, b| {
+        b.result
+            .similarity
+            .unwrap_or(0.0)
+            .total_cmp(&a.result.similarity.unwrap_or(0.0))
+    });
+    let best = &hits[0].result;
+
+    let mut engines: Vec<String> = Vec::new();
+    let mut info = AdditionalInfo::default();
+    for hit in &hits {
+        if !engines.contains(&hit.engine) {
+            engines.push(hit.engine.clone());
+        }
+        let result = &hit.result;
+        if result.url != best.url {
+            push_unique(&mut info.ext_urls, &result.url);
+        }
+        if let Some(other) = &result.additional_info {
+            union_info(&mut info, other, &best.url);
+        }
+    }
+
+    let max = |f: fn(&SearchResult) -> Option<f32>| {
+        hits.iter()
+            .filter_map(|hit| f(&hit.result))
+            .reduce(f32::max)
+    };
+    let source = hits
+        .iter()
+        .flat_map(|hit| urls(&hit.result))
+        .find_map(SourceRef::parse);
+    MergedResult {
+        url: best.url.clone(),
+        source,
+        title: hits
+            .iter()
+            .find_map(|hit| hit.result.title.clone().filter(|t| !t.is_empty())),
+        thumbnail: hits.iter().find_map(|hit| hit.result.thumbnail.clone()),
+        similarity: max(|r| r.similarity),
+        local_similarity: max(|r| r.local_similarity),
+        engines,
+        additional_info: info,
+        hits,
+    }
+}
+
+fn union_info(info: &mut AdditionalInfo, other: &AdditionalInfo, primary: &str) {
+    info.author = info.author.take().or_else(|| other.author.clone());
+    info.author_url = info.author_url.take().or_else(|| other.author_url.clone());
+    info.source_url = info.source_url.take().or_else(|| other.source_url.clone());
+    info.site = info.site.take().or_else(|| other.site.clone());
+    info.post_id = info.post_id.take().or_else(|| other.post_id.clone());
+    info.match_tier = info.match_tier.take().or(other.match_tier);
+    info.created_at = info.created_at.take().or_else(|| other.created_at.clone());
+    info.snippet = info.snippet.take().or_else(|| other.snippet.clone());
+    info.language = info.language.take().or_else(|| other.language.clone());
+    info.page_url = info.page_url.take().or_else(|| other.page_url.clone());
+    // 尺寸取最大的那个，通常对应原图
+    info.size = match (info.size, other.size) {
+        (Some(a), Some(b)) => Some(if area(b) > area(a) { b } else { a }),
+        (a, b) => a.or(b),
+    };
+    // 分级取最严格的
+    info.rating = info.rating.max(other.rating);
+    for tag in &other.tags {
+        push_unique(&mut info.tags, tag);
+    }
+    for url in &other.ext_urls {
+        if url != primary {
+            push_unique(&mut info.ext_urls, url);
+        }
+    }
+    for (key, value) in &other.extra {
+        info.extra
+            .entry(key.clone())
+            .or_insert_with(|| value.clone());
+    }
+}
+
+fn area((width, height): (u32, u32)) -> u64 {
+    width as u64 * height as u64
+}
+
+fn push_unique(list: &mut Vec<String>, value: &str) {
+    if !value.is_empty() && !list.iter().any(|v| v == value) {
+        list.push(value.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Rating;
+
+    fn result(url: &str, similarity: f32, info: AdditionalInfo) -> SearchResult {
+        SearchResult {
+            similarity: Some(similarity),
+            additional_info: Some(info),
+            ..SearchResult::new(url, "")
+        }
+    }
+
+    #[test]
+    fn test_merge_groups_by_shared_url() {
+        let saucenao = result(
+            "https://www.pixiv.net/artworks/123",
+            92.0,
+            AdditionalInfo {
+                author: Some("artist".to_string()),
+                ext_urls: vec!["https://danbooru.donmai.us/posts/456".to_string()],
+                ..Default::default()
+            },
+        );
+        let iqdb = result(
+            "https://danbooru.donmai.us/post/show/456",
+            88.0,
+            AdditionalInfo {
+                tags: vec!["1girl".to_string()],
+                size: Some((1200, 1600)),
+                rating: Some(Rating::Safe),
+                ..Default::default()
+            },
+        );
+        let other = result("https://example.com/other", 40.0, AdditionalInfo::default());
+
+        let merged = merge([("SauceNAO", vec![saucenao]), ("IQDB", vec![iqdb, other])]);
+        assert_eq!(merged.len(), 2);
+
+        let first = &merged[0];
+        assert_eq!(first.url, "https://www.pixiv.net/artworks/123");
+        assert_eq!(first.engines, vec!["SauceNAO", "IQDB"]);
+        assert_eq!(
+            first.source.as_ref().map(ToString::to_string).as_deref(),
+            Some("Pixiv:123")
+        );
+        assert_eq!(first.similarity, Some(92.0));
+        assert_eq!(first.additional_info.author.as_deref(), Some("artist"));
+        assert_eq!(first.additional_info.tags, vec!["1girl"]);
+        assert_eq!(first.additional_info.size, Some((1200, 1600)));
+        assert_eq!(first.hits.len(), 2);
+    }
+
+    #[test]
+    fn test_merge_keeps_hits_from_same_site_apart() {
+        // Yandex 的两条结果来自同一站点，只有域名相同
+        let yandex = |url: &str, image: &str| SearchResult {
+            similarity: None,
+            ..result(
+                url,
+                0.0,
+                AdditionalInfo {
+                    source_url: Some(image.to_string()),
+                    site: Some("twitter.com".to_string()),
+                    ext_urls: vec!["https://twitter.com/".to_string()],
+                    ..Default::default()
+                },
+            )
+        };
+        let merged = merge([(
+            "Yandex",
+            vec![
+                yandex(
+                    "https://twitter.com/a/status/1",
+                    "https://pbs.twimg.com/media/AAA.jpg",
+                ),
+                yandex(
+                    "https://twitter.com/b/status/2",
+                    "https://pbs.twimg.com/media/BBB.jpg",
+                ),
+            ],
+        )]);
+        assert_eq!(merged.len(), 2);
+    }
+}
@@ -1,16 +1,96 @@
-use crate::error::Result;
+use crate::block::{self, Block};
+use crate::cassette::{self, Mode, RecordedRequest};
+use crate::error::{Error, Result};
+use crate::ratelimit::{self, RatePermit};
+use crate::retry::{self, RetryPolicy};
+use crate::types::SearchOptions;
 use bytes::Bytes;
+use futures::StreamExt;
 use log::debug;
 use reqwest::header::HeaderMap;
-use reqwest::{Client, Response};
+use reqwest::{Client, RequestBuilder, Response};
+use reqwest_cookie_store::CookieStoreMutex;
+use std::sync::Arc;
 use std::time::Duration;
 
 const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+/// 下载远程图片时允许的最大字节数
+pub const MAX_DOWNLOAD_SIZE: usize = 20 * 1024 * 1024;
 const DEFAULT_USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/121.0.0.0 Safari/537.36";
 
+/// multipart 表单中的文件字段
+#[derive(Debug, Clone)]
+pub struct Part {
+    pub bytes: Bytes,
+    pub file_name: String,
+    pub mime: &'static str,
+}
+
+/// 可重复构造的 multipart 表单
+///
+/// reqwest 的表单发送一次后即被消耗，重试时需要用这里保存的字段重新生成
+#[derive(Debug, Clone, Default)]
+pub struct Form {
+    texts: Vec<(String, String)>,
+    parts: Vec<(String, Part)>,
+}
+
+impl Form {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn text(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.texts.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn part(mut self, name: impl Into<String>, part: Part) -> Self {
+        self.parts.push((name.into(), part));
+        self
+    }
+
+    pub fn texts(&self) -> &[(String, String)] {
+        &self.texts
+    }
+
+    pub fn parts(&self) -> &[(String, Part)] {
+        &self.parts
+    }
+
+    fn build(&self) -> Result<reqwest::multipart::Form> {
+        let mut form = reqwest::multipart::Form::new();
+        for (name, value) in &self.texts {
+            form = form.text(name.clone(), value.clone());
+        }
+        for (name, part) in &self.parts {
+            let body = reqwest::Body::from(part.bytes.clone());
+            let file = reqwest::multipart::Part::stream_with_length(body, part.bytes.len() as u64)
+                .file_name(part.file_name.clone())
+                .mime_str(part.mime)?;
+            form = form.part(name.clone(), file);
+        }
+        Ok(form)
+    }
+}
+
+/// 附在响应上的并发名额，读取正文时取出并持有到读取结束
+#[derive(Debug, Clone)]
+pub(crate) struct HeldPermit {
+    _permit: Arc<RatePermit>,
+}
+
+/// 取出响应上的并发名额，调用方在正文读取完毕之前持有返回值
+pub(crate) fn hold_permit(response: &mut Response) -> Option<HeldPermit> {
+    response.extensions_mut().remove::<HeldPermit>()
+}
+
 #[derive(Clone)]
 pub struct Network {
     client: Client,
+    cookie_store: Option<Arc<CookieStoreMutex>>,
+    // 引擎名，用于查找限速与重试规则
+    engine: Option<&'static str>,
 }
 
 impl std::fmt::Debug for Network {
@@ -33,71 +113,338 @@ impl Network {
             .build()
             .expect("Failed to create HTTP client");
 
-        Self { client }
+        Self {
+            client,
+            cookie_store: None,
+            engine: None,
+        }
+    }
+
+    /// 按照搜索选项中的代理与超时设置创建客户端
+    pub fn from_options(options: &SearchOptions) -> Result<Self> {
+        let mut builder = Client::builder()
+            .timeout(options.timeout.map_or(DEFAULT_TIMEOUT, Duration::from_secs))
+            .user_agent(DEFAULT_USER_AGENT);
+        if let Some(proxy) = &options.proxy {
+            builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+        }
+
+        Ok(Self {
+            client: builder.build()?,
+            cookie_store: None,
+            engine: None,
+        })
     }
 
     pub fn with_client(client: Client) -> Self {
-        Self { client }
+        Self {
+            client,
+            cookie_store: None,
+            engine: None,
+        }
+    }
+
+    /// 使用共享的 cookie 存储创建客户端，响应中的 Set-Cookie 会写回该存储
+    pub fn with_cookie_store(cookie_store: Arc<CookieStoreMutex>) -> Self {
+        let client = Client::builder()
+            .timeout(DEFAULT_TIMEOUT)
+            .user_agent(DEFAULT_USER_AGENT)
+            .cookie_provider(Arc::clone(&cookie_store))
+            .build()
+            .expect("Failed to create HTTP client");
+
+        Self {
+            client,
+            cookie_store: Some(cookie_store),
+            engine: None,
+        }
+    }
+
+    pub fn cookie_store(&self) -> Option<&Arc<CookieStoreMutex>> {
+        self.cookie_store.as_ref()
+    }
+
+    /// 按引擎名使用共享的限速器与重试规则，同一引擎的所有请求都会排队
+    ///
+    /// 规则在发送时查找，`set_rate_limit` 与 `set_retry_policy` 对已创建的引擎同样生效
+    pub fn for_engine(mut self, engine: &'static str) -> Self {
+        self.engine = Some(engine);
+        self
+    }
+
+    /// 底层客户端，用于构造需要额外参数的请求，构造好的请求交给 `send` 发送
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// 发送请求，配置了引擎时先排队，遇到临时错误按重试规则重发
+    ///
+    /// 引擎挂有磁带（见 `cassette::set_cassette`）时改为录制或回放
+    pub async fn send(&self, request: RequestBuilder) -> Result<Response> {
+        self.execute(request, None).await
+    }
+
+    /// 附带 multipart 表单发送请求，每次重试都会重新生成表单
+    pub async fn send_form(&self, request: RequestBuilder, form: &Form) -> Result<Response> {
+        self.execute(request, Some(form)).await
+    }
+
+    async fn execute(&self, request: RequestBuilder, form: Option<&Form>) -> Result<Response> {
+        let Some(cassette) = self.engine.and_then(cassette::cassette) else {
+            return self.send_with_retry(request, form).await;
+        };
+        let (client, request) = request.build_split();
+        let request = request?;
+        let recorded = RecordedRequest::new(request.method(), request.url(), form);
+        match cassette.mode() {
+            Mode::Record => {
+                let request = RequestBuilder::from_parts(client, request);
+                let response = self.send_with_retry(request, form).await?;
+                cassette.save(recorded, response).await
+            }
+            Mode::Replay => {
+                // 回放时不经过 reqwest，需要手动把 Set-Cookie 写入共享的 cookie 存储
+                let response = cassette.find(&recorded)?;
+                self.store_cookies(&response);
+                Ok(response)
+            }
+        }
+    }
+
+    fn store_cookies(&self, response: &Response) {
+        let Some(cookie_store) = &self.cookie_store else {
+            return;
+        };
+        let mut store = cookie_store.lock().unwrap_or_else(|e| e.into_inner());
+        for value in response.headers().get_all(reqwest::header::SET_COOKIE) {
+            if let Ok(cookie) = value.to_str() {
+                let _ = store.parse(cookie, response.url());
+            }
+        }
+    }
+
+    async fn send_with_retry(
+        &self,
+        request: RequestBuilder,
+        form: Option<&Form>,
+    ) -> Result<Response> {
+        let policy = match self.engine {
+            Some(engine) => retry::policy(engine),
+            None => Arc::new(RetryPolicy::none()),
+        };
+        let mut pending = request;
+        let mut attempt = 1;
+        loop {
+            // 请求体为流时无法复制，只能发送一次
+            let next = if attempt < policy.max_attempts {
+                pending.try_clone()
+            } else {
+                None
+            };
+            let request = match form {
+                Some(form) => pending.multipart(form.build()?),
+                None => pending,
+            };
+
+            let permit = self.acquire().await?;
+            let mut result = request.send().await;
+            // 并发名额随响应一起返回，正文读取完毕后才释放
+            if let (Ok(response), Some(permit)) = (&mut result, permit) {
+                response.extensions_mut().insert(HeldPermit {
+                    _permit: Arc::new(permit),
+                });
+            }
+
+            let delay = match &result {
+                Ok(response) if policy.retries_status(response.status()) => {
+                    policy.delay_for(attempt, response.headers())
+                }
+                Err(e) if retry::is_transient(e) => Some(policy.backoff(attempt)),
+                _ => None,
+            };
+            match (delay, next) {
+                (Some(delay), Some(next)) => {
+                    match &result {
+                        Ok(response) => debug!("HTTP {}，{:?} 后重试", response.status(), delay),
+                        Err(e) => debug!("请求失败: {}，{:?} 后重试", e, delay),
+                    }
+                    tokio::time::sleep(delay).await;
+                    pending = next;
+                    attempt += 1;
+                }
+                _ => {
+                    return result.map_err(|e| match self.engine {
+                        Some(engine) => Error::from(e).with_engine(engine),
+                        None => e.into(),
+                    })
+                }
+            }
+        }
+    }
+
+    async fn acquire(&self) -> Result<Option<RatePermit>> {
+        match self.engine {
+            Some(engine) => Ok(Some(ratelimit::limiter(engine).acquire().await?)),
+            None => Ok(None),
+        }
+    }
+
+    /// 读取响应正文，先检查是否为验证码、Cloudflare 等拦截页面或空响应
+    pub async fn read_text(&self, mut response: Response) -> Result<String> {
+        let _permit = hold_permit(&mut response);
+        let url = response.url().to_string();
+        let status = response.status().as_u16();
+        let headers = response.headers().clone();
+        let text = response.text().await?;
+        match block::detect(&url, status, &headers, &text) {
+            Some(block) => Err(self.blocked(block)),
+            None => Ok(text),
+        }
+    }
+
+    /// 拦截页面转换为错误，没有配置引擎时报告为无效响应
+    fn blocked(&self, block: Block) -> Error {
+        match self.engine {
+            Some(engine) => block.into_error(engine),
+            None => Error::InvalidResponse(block.reason().to_string()),
+        }
+    }
+
+    /// 读取并解析 JSON 正文，拦截检查同 `read_text`
+    pub async fn read_json<T: serde::de::DeserializeOwned>(&self, response: Response) -> Result<T> {
+        Ok(serde_json::from_str(&self.read_text(response).await?)?)
     }
 
     pub async fn get(&self, url: &str) -> Result<Response> {
         debug!("GET request to: {}", url);
-        Ok(self.client.get(url).send().await?)
+        self.send(self.client.get(url)).await
     }
 
     pub async fn get_with_headers(&self, url: &str, headers: HeaderMap) -> Result<Response> {
         debug!("GET request to: {} with headers: {:?}", url, headers);
-        Ok(self.client.get(url).headers(headers).send().await?)
+        self.send(self.client.get(url).headers(headers)).await
     }
 
     pub async fn post(&self, url: &str, body: Vec<u8>) -> Result<Response> {
         debug!("POST request to: {}", url);
-        Ok(self.client.post(url).body(body).send().await?)
+        self.send(self.client.post(url).body(body)).await
     }
 
     pub async fn post_json<T: serde::Serialize>(&self, url: &str, json: &T) -> Result<Response> {
         debug!("POST JSON request to: {}", url);
-        Ok(self.client.post(url).json(json).send().await?)
+        self.send(self.client.post(url).json(json)).await
     }
 
     pub async fn get_bytes(&self, url: &str) -> Result<Bytes> {
-        Ok(self.get(url).await?.bytes().await?)
+        let mut response = self.get(url).await?;
+        let _permit = hold_permit(&mut response);
+        Ok(response.bytes().await?)
     }
 
-    pub async fn post_multipart(
-        &self,
-        url: &str,
-        form: reqwest::multipart::Form,
-    ) -> Result<Response> {
+    /// 下载远程文件，超过 `max_size` 字节时中止并返回错误
+    pub async fn download(&self, url: &str, max_size: usize) -> Result<Bytes> {
+        let mut response = self.get(url).await?;
+        let _permit = hold_permit(&mut response);
+        if !response.status().is_success() {
+            return Err(Error::InvalidResponse(format!(
+                "下载 {} 失败: HTTP {}",
+                url,
+                response.status()
+            )));
+        }
+        if response
+            .content_length()
+            .is_some_and(|len| len > max_size as u64)
+        {
+            return Err(Error::InvalidResponse(format!(
+                "文件超过大小限制 {} 字节",
+                max_size
+            )));
+        }
+
+        let mut buf = Vec::new();
+        let mut stream = response.bytes_stream();
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk?;
+            if buf.len() + chunk.len() > max_size {
+                return Err(Error::InvalidResponse(format!(
+                    "文件超过大小限制 {} 字节",
+                    max_size
+                )));
+            }
+            buf.extend_from_slice(&chunk);
+        }
+        if buf.is_empty() {
+            return Err(self.blocked(Block::Empty));
+        }
+        Ok(Bytes::from(buf))
+    }
+
+    pub async fn post_multipart(&self, url: &str, form: &Form) -> Result<Response> {
         debug!("POST multipart request to: {}", url);
-        Ok(self.client.post(url).multipart(form).send().await?)
+        self.send_form(self.client.post(url), form).await
     }
 
     pub async fn post_multipart_with_headers(
         &self,
         url: &str,
-        form: reqwest::multipart::Form,
+        form: &Form,
         headers: HeaderMap,
     ) -> Result<Response> {
         debug!(
             "POST multipart request to: {} with headers: {:?}",
             url, headers
         );
-        Ok(self
-            .client
-            .post(url)
-            .headers(headers)
-            .multipart(form)
-            .send()
-            .await?)
+        self.send_form(self.client.post(url).headers(headers), form)
+            .await
     }
 
     pub fn set_proxy(&mut self, proxy_url: &str) -> Result<()> {
-        self.client = Client::builder()
+        let mut builder = Client::builder()
             .timeout(DEFAULT_TIMEOUT)
             .user_agent(DEFAULT_USER_AGENT)
-            .proxy(reqwest::Proxy::all(proxy_url)?)
-            .build()?;
+            .proxy(reqwest::Proxy::all(proxy_url)?);
+        if let Some(cookie_store) = &self.cookie_store {
+            builder = builder.cookie_provider(Arc::clone(cookie_store));
+        }
+        self.client = builder.build()?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ratelimit::{set_rate_limit, RateLimit};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_permit_held_until_body_is_read() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let mut buf = [0; 1024];
+                    let _ = socket.read(&mut buf).await;
+                    let _ = socket
+                        .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok")
+                        .await;
+                });
+            }
+        });
+
+        set_rate_limit("Permit Test", RateLimit::unlimited().with_max_concurrent(1));
+        let network = Network::new().for_engine("Permit Test");
+        let first = network.get(&url).await.unwrap();
+
+        // 第一个响应的正文还没有读取，第二个请求只能排队
+        let second = tokio::time::timeout(Duration::from_millis(200), network.get(&url)).await;
+        assert!(second.is_err());
+
+        assert_eq!(network.read_text(first).await.unwrap(), "ok");
+        let second = network.get(&url).await.unwrap();
+        assert_eq!(network.read_text(second).await.unwrap(), "ok");
+    }
+}
@@ -0,0 +1,638 @@
+use crate::error::{Error, Result};
+use crate::network::Part;
+use crate::types::CropRect;
+use bytes::Bytes;
+use image::codecs::jpeg::JpegEncoder;
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader, Rgb};
+use log::debug;
+use std::io::Cursor;
+
+/// 重新编码为 JPEG 时依次尝试的质量
+const JPEG_QUALITIES: [u8; 4] = [90, 80, 70, 60];
+
+/// 边框像素与参考颜色的最大通道差
+const BORDER_TOLERANCE: u8 = 24;
+
+/// 一行（列）中至少有这么多比例的像素接近参考颜色才视为边框，容忍 JPEG 噪点
+const BORDER_RATIO: f32 = 0.98;
+
+/// 裁剪后每个方向至少保留的比例，避免把纯色图片裁没
+const MIN_REMAINING_RATIO: f32 = 0.2;
+
+/// 引擎对上传图片的限制，由各引擎通过 `ImageSearch::upload_constraints` 声明
+#[derive(Debug, Clone)]
+pub struct UploadConstraints {
+    /// 引擎能够直接处理的格式，其他格式会被转换
+    pub formats: &'static [ImageFormat],
+    /// 最长边的像素上限
+    pub max_dimension: Option<u32>,
+    /// 文件大小上限（字节）
+    pub max_bytes: Option<usize>,
+}
+
+impl Default for UploadConstraints {
+    fn default() -> Self {
+        Self {
+            formats: &[ImageFormat::Jpeg, ImageFormat::Png],
+            max_dimension: None,
+            max_bytes: None,
+        }
+    }
+}
+
+impl UploadConstraints {
+    fn accepts(&self, format: ImageFormat) -> bool {
+        self.formats.contains(&format)
+    }
+
+    fn fits(&self, (width, height): (u32, u32), len: usize) -> bool {
+        self.max_dimension
+            .is_none_or(|max| width <= max && height <= max)
+            && self.max_bytes.is_none_or(|max| len <= max)
+    }
+}
+
+/// 预处理后可直接上传的图片
+#[derive(Debug, Clone)]
+pub struct PreparedImage {
+    pub bytes: Vec<u8>,
+    pub format: ImageFormat,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl PreparedImage {
+    pub fn mime(&self) -> &'static str {
+        self.format.to_mime_type()
+    }
+
+    pub fn file_name(&self) -> String {
+        let ext = self.format.extensions_str().first().unwrap_or(&"bin");
+        format!("image.{}", ext)
+    }
+
+    /// 转换为 multipart 表单中的文件字段
+    pub fn into_part(self) -> Part {
+        Part {
+            file_name: self.file_name(),
+            mime: self.mime(),
+            bytes: Bytes::from(self.bytes),
+        }
+    }
+}
+
+/// 根据文件头判断真实格式
+///
+/// AVIF 需要 image 的 `avif-native` 特性（依赖系统的 dav1d），本项目未启用，
+/// 与 HEIC 一样需要调用方先解码后使用 `prepare_decoded`
+pub fn sniff(bytes: &[u8]) -> Result<ImageFormat> {
+    // HEIC/HEIF 与 AVIF 共用 ISO BMFF 容器，许多 AVIF 的主品牌是通用的 `mif1`，
+    // 需要连同兼容品牌一起判断
+    let brands = ftyp_brands(bytes);
+    if brands
+        .iter()
+        .any(|&brand| matches!(brand, b"avif" | b"avis"))
+    {
+        return Err(Error::UnsupportedImage(
+            "无法解码 AVIF 图片，请先自行解码后使用 prepare_decoded".to_string(),
+        ));
+    }
+    if brands.iter().any(|&brand| {
+        matches!(
+            brand,
+            b"heic" | b"heix" | b"hevc" | b"hevx" | b"heim" | b"heis" | b"mif1" | b"msf1"
+        )
+    }) {
+        return Err(Error::UnsupportedImage(
+            "HEIC/HEIF 图片需要先解码，请使用 prepare_decoded".to_string(),
+        ));
+    }
+    let format = image::guess_format(bytes)?;
+    if format == ImageFormat::Avif || !format.reading_enabled() {
+        return Err(Error::UnsupportedImage(format!(
+            "无法解码 {:?} 图片，请先自行解码后使用 prepare_decoded",
+            format
+        )));
+    }
+    Ok(format)
+}
+
+/// ISO BMFF `ftyp` 盒中的主品牌与兼容品牌，不是该容器时为空
+fn ftyp_brands(bytes: &[u8]) -> Vec<&[u8; 4]> {
+    if bytes.len() < 16 || &bytes[4..8] != b"ftyp" {
+        return Vec::new();
+    }
+    let size = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize;
+    let end = size.clamp(16, bytes.len());
+    // 主品牌之后是 4 字节的次版本号，然后是兼容品牌列表
+    std::iter::once(&bytes[8..12])
+        .chain(bytes[16..end].chunks_exact(4))
+        .filter_map(|brand| brand.try_into().ok())
+        .collect()
+}
+
+/// 按引擎限制处理图片：转换不支持的格式、缩小尺寸并去除 EXIF
+pub fn prepare(bytes: &[u8], constraints: &UploadConstraints) -> Result<PreparedImage> {
+    let format = sniff(bytes)?;
+    let reader = ImageReader::with_format(Cursor::new(bytes), format);
+    let mut decoder = reader.into_decoder()?;
+    let dimensions = decoder.dimensions();
+    let orientation = decoder.orientation()?;
+
+    // 格式与尺寸都满足要求且无需旋转时保留原始编码，避免有损的重新压缩
+    if constraints.accepts(format)
+        && constraints.fits(dimensions, bytes.len())
+        && orientation == image::metadata::Orientation::NoTransforms
+    {
+        return Ok(PreparedImage {
+            bytes: strip_metadata(bytes, format),
+            format,
+            width: dimensions.0,
+            height: dimensions.1,
+        });
+    }
+
+    debug!(
+        "重新编码 {:?} 图片 ({}x{}, {} 字节)",
+        format,
+        dimensions.0,
+        dimensions.1,
+        bytes.len()
+    );
+    // GIF 等动图只取第一帧
+    let mut img = DynamicImage::from_decoder(decoder)?;
+    img.apply_orientation(orientation);
+    prepare_decoded(img, constraints)
+}
+
+/// 解码图片并按 EXIF 方向旋转，得到与肉眼所见一致的像素
+pub fn decode(bytes: &[u8]) -> Result<DynamicImage> {
+    let format = sniff(bytes)?;
+    let mut decoder = ImageReader::with_format(Cursor::new(bytes), format).into_decoder()?;
+    let orientation = decoder.orientation()?;
+    let mut img = DynamicImage::from_decoder(decoder)?;
+    img.apply_orientation(orientation);
+    Ok(img)
+}
+
+/// 裁掉纯色边框与黑边，返回裁剪后的 PNG 与裁剪区域；没有可裁的边框时返回 `None`
+pub fn auto_crop(bytes: &[u8]) -> Result<Option<(Vec<u8>, CropRect)>> {
+    let img = decode(bytes)?;
+    let Some(rect) = detect_borders(&img) else {
+        return Ok(None);
+    };
+
+    debug!(
+        "裁掉边框: {}x{} -> {}x{}+{}+{}",
+        img.width(),
+        img.height(),
+        rect.width,
+        rect.height,
+        rect.x,
+        rect.y
+    );
+    let cropped = img.crop_imm(rect.x, rect.y, rect.width, rect.height);
+    // 使用无损编码，之后还会按引擎限制再处理一次
+    Ok(Some((encode_png(&cropped)?, rect)))
+}
+
+/// 检测四周的纯色边框（黑边、白边等），返回内容区域
+pub fn detect_borders(img: &DynamicImage) -> Option<CropRect> {
+    let rgb = &img.to_rgb8();
+    let (width, height) = rgb.dimensions();
+    if width < 3 || height < 3 {
+        return None;
+    }
+
+    let row = |y: u32, x0: u32, x1: u32| (x0..x1).map(move |x| *rgb.get_pixel(x, y));
+    let col = |x: u32, y0: u32, y1: u32| (y0..y1).map(move |y| *rgb.get_pixel(x, y));
+
+    // 先确定上下边框，再在剩余的行范围内确定左右边框
+    let top = border_len(height, |i| row(i, 0, width));
+    let bottom = border_len(height - top, |i| row(height - 1 - i, 0, width));
+    let (y0, y1) = (top, height - bottom);
+    if y1 <= y0 {
+        return None;
+    }
+    let left = border_len(width, |i| col(i, y0, y1));
+    let right = border_len(width - left, |i| col(width - 1 - i, y0, y1));
+    let (x0, x1) = (left, width - right);
+
+    let rect = CropRect {
+        x: x0,
+        y: y0,
+        width: x1.saturating_sub(x0),
+        height: y1 - y0,
+    };
+    let too_small = (rect.width as f32) < width as f32 * MIN_REMAINING_RATIO
+        || (rect.height as f32) < height as f32 * MIN_REMAINING_RATIO;
+    if too_small || (rect.width, rect.height) == (width, height) {
+        return None;
+    }
+    Some(rect)
+}
+
+/// 从外向内数连续的纯色行（列），参考颜色取最外侧一行的中位数
+fn border_len<I>(limit: u32, line: impl Fn(u32) -> I) -> u32
+where
+    I: Iterator<Item = Rgb<u8>>,
+{
+    let Some(reference) = median_color(line(0)) else {
+        return 0;
+    };
+
+    let mut len = 0;
+    while len < limit {
+        if !is_uniform(line(len), reference) {
+            break;
+        }
+        len += 1;
+    }
+    len
+}
+
+/// 判断一行（列）像素是否基本都接近参考颜色
+pub(crate) fn is_uniform(pixels: impl Iterator<Item = Rgb<u8>>, reference: Rgb<u8>) -> bool {
+    let (mut total, mut close) = (0usize, 0usize);
+    for pixel in pixels {
+        total += 1;
+        if color_distance(pixel, reference) <= BORDER_TOLERANCE {
+            close += 1;
+        }
+    }
+    total > 0 && close as f32 >= total as f32 * BORDER_RATIO
+}
+
+pub(crate) fn median_color(pixels: impl Iterator<Item = Rgb<u8>>) -> Option<Rgb<u8>> {
+    let mut channels: [Vec<u8>; 3] = Default::default();
+    for Rgb(pixel) in pixels {
+        for (channel, value) in channels.iter_mut().zip(pixel) {
+            channel.push(value);
+        }
+    }
+    if channels[0].is_empty() {
+        return None;
+    }
+    let mut median = [0u8; 3];
+    for (out, channel) in median.iter_mut().zip(channels.iter_mut()) {
+        channel.sort_unstable();
+        *out = channel[channel.len() / 2];
+    }
+    Some(Rgb(median))
+}
+
+fn color_distance(Rgb(a): Rgb<u8>, Rgb(b): Rgb<u8>) -> u8 {
+    a.iter()
+        .zip(b.iter())
+        .map(|(a, b)| a.abs_diff(*b))
+        .max()
+        .unwrap_or(0)
+}
+
+/// 处理已经解码的图片，可用于 image 不支持的格式（例如由调用方解码的 HEIC）
+pub fn prepare_decoded(
+    img: DynamicImage,
+    constraints: &UploadConstraints,
+) -> Result<PreparedImage> {
+    let mut img = match constraints.max_dimension {
+        Some(max) if img.width() > max || img.height() > max => {
+            img.resize(max, max, image::imageops::FilterType::Lanczos3)
+        }
+        _ => img,
+    };
+
+    // 带透明通道时优先使用 PNG，否则使用体积更小的 JPEG
+    let use_png = img.color().has_alpha() && constraints.accepts(ImageFormat::Png)
+        || !constraints.accepts(ImageFormat::Jpeg);
+
+    loop {
+        let encoded = if use_png {
+            encode_png(&img)?
+        } else {
+            let mut last = Vec::new();
+            for quality in JPEG_QUALITIES {
+                last = encode_jpeg(&img, quality)?;
+                if constraints.max_bytes.is_none_or(|max| last.len() <= max) {
+                    break;
+                }
+            }
+            last
+        };
+
+        if constraints.max_bytes.is_none_or(|max| encoded.len() <= max) {
+            return Ok(PreparedImage {
+                bytes: encoded,
+                format: if use_png {
+                    ImageFormat::Png
+                } else {
+                    ImageFormat::Jpeg
+                },
+                width: img.width(),
+                height: img.height(),
+            });
+        }
+
+        // 仍然超出大小限制时逐步缩小
+        let (width, height) = (img.width() * 3 / 4, img.height() * 3 / 4);
+        if width < 16 || height < 16 {
+            return Err(Error::UnsupportedImage(
+                "无法将图片压缩到引擎的大小限制以内".to_string(),
+            ));
+        }
+        img = img.resize(width, height, image::imageops::FilterType::Lanczos3);
+    }
+}
+
+pub(crate) fn encode_png(img: &DynamicImage) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    img.write_to(&mut Cursor::new(&mut buf), ImageFormat::Png)?;
+    Ok(buf)
+}
+
+fn encode_jpeg(img: &DynamicImage, quality: u8) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    let encoder = JpegEncoder::new_with_quality(&mut buf, quality);
+    img.to_rgb8().write_with_encoder(encoder)?;
+    Ok(buf)
+}
+
+/// 去除 EXIF 等元数据，不重新编码像素
+fn strip_metadata(bytes: &[u8], format: ImageFormat) -> Vec<u8> {
+    match format {
+        ImageFormat::Jpeg => strip_jpeg_metadata(bytes),
+        ImageFormat::Png => strip_png_metadata(bytes),
+        ImageFormat::WebP => strip_webp_metadata(bytes),
+        ImageFormat::Gif => strip_gif_metadata(bytes),
+        _ => None,
+    }
+    .unwrap_or_else(|| bytes.to_vec())
+}
+
+/// 删除 JPEG 中的 APP1（EXIF/XMP）段
+fn strip_jpeg_metadata(bytes: &[u8]) -> Option<Vec<u8>> {
+    if !bytes.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+
+    let mut out = vec![0xFF, 0xD8];
+    let mut pos = 2;
+    while pos + 4 <= bytes.len() {
+        if bytes[pos] != 0xFF {
+            return None;
+        }
+        let marker = bytes[pos + 1];
+        // SOS 之后是压缩数据，原样保留
+        if marker == 0xDA {
+            out.extend_from_slice(&bytes[pos..]);
+            return Some(out);
+        }
+        let len = u16::from_be_bytes([bytes[pos + 2], bytes[pos + 3]]) as usize;
+        let end = pos + 2 + len;
+        if len < 2 || end > bytes.len() {
+            return None;
+        }
+        if marker != 0xE1 {
+            out.extend_from_slice(&bytes[pos..end]);
+        }
+        pos = end;
+    }
+    None
+}
+
+/// 删除 PNG 中的 eXIf 与文本块
+fn strip_png_metadata(bytes: &[u8]) -> Option<Vec<u8>> {
+    const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+    if !bytes.starts_with(SIGNATURE) {
+        return None;
+    }
+
+    let mut out = SIGNATURE.to_vec();
+    let mut pos = SIGNATURE.len();
+    while pos + 8 <= bytes.len() {
+        let len = u32::from_be_bytes(bytes[pos..pos + 4].try_into().ok()?) as usize;
+        let kind = &bytes[pos + 4..pos + 8];
+        // 长度 + 类型 + 数据 + CRC
+        let end = pos + 12 + len;
+        if end > bytes.len() {
+            return None;
+        }
+        if !matches!(kind, b"eXIf" | b"tEXt" | b"zTXt" | b"iTXt") {
+            out.extend_from_slice(&bytes[pos..end]);
+        }
+        pos = end;
+    }
+    Some(out)
+}
+
+/// 删除 WebP 中的 EXIF 与 XMP 块，并清除 VP8X 中对应的标志位
+fn strip_webp_metadata(bytes: &[u8]) -> Option<Vec<u8>> {
+    if bytes.len() < 12 || &bytes[..4] != b"RIFF" || &bytes[8..12] != b"WEBP" {
+        return None;
+    }
+
+    let mut out = bytes[..12].to_vec();
+    let mut pos = 12;
+    while pos + 8 <= bytes.len() {
+        let kind = &bytes[pos..pos + 4];
+        let len = u32::from_le_bytes(bytes[pos + 4..pos + 8].try_into().ok()?) as usize;
+        // 类型 + 长度 + 数据，奇数长度补一个字节
+        let end = pos + 8 + len + len % 2;
+        if end > bytes.len() {
+            return None;
+        }
+        match kind {
+            b"EXIF" | b"XMP " => {}
+            b"VP8X" if len >= 1 => {
+                let start = out.len();
+                out.extend_from_slice(&bytes[pos..end]);
+                // 第一个字节中 0x08 为 EXIF 标志，0x04 为 XMP 标志
+                out[start + 8] &= !0x0C;
+            }
+            _ => out.extend_from_slice(&bytes[pos..end]),
+        }
+        pos = end;
+    }
+    if pos != bytes.len() {
+        return None;
+    }
+
+    let riff_len = u32::try_from(out.len() - 8).ok()?;
+    out[4..8].copy_from_slice(&riff_len.to_le_bytes());
+    Some(out)
+}
+
+/// 删除 GIF 中的注释扩展与应用扩展（XMP 等），保留控制循环播放的 NETSCAPE2.0
+fn strip_gif_metadata(bytes: &[u8]) -> Option<Vec<u8>> {
+    if !bytes.starts_with(b"GIF87a") && !bytes.starts_with(b"GIF89a") {
+        return None;
+    }
+
+    // 文件头 + 逻辑屏幕描述符，之后可能跟着全局颜色表
+    let flags = *bytes.get(10)?;
+    let mut pos = 13 + color_table_len(flags);
+    let mut out = bytes.get(..pos)?.to_vec();
+    loop {
+        match *bytes.get(pos)? {
+            // 扩展块：引导符 + 标签 + 子块
+            0x21 => {
+                let label = *bytes.get(pos + 1)?;
+                let end = skip_sub_blocks(bytes, pos + 2)?;
+                let keep = match label {
+                    0xFE => false,
+                    0xFF => bytes.get(pos + 3..pos + 14) == Some(b"NETSCAPE2.0".as_slice()),
+                    _ => true,
+                };
+                if keep {
+                    out.extend_from_slice(&bytes[pos..end]);
+                }
+                pos = end;
+            }
+            // 图像块：描述符 + 局部颜色表 + LZW 最小码长 + 子块
+            0x2C => {
+                let flags = *bytes.get(pos + 9)?;
+                let data = pos + 10 + color_table_len(flags) + 1;
+                let end = skip_sub_blocks(bytes, data)?;
+                out.extend_from_slice(&bytes[pos..end]);
+                pos = end;
+            }
+            0x3B => {
+                out.push(0x3B);
+                return Some(out);
+            }
+            _ => return None,
+        }
+    }
+}
+
+fn color_table_len(flags: u8) -> usize {
+    if flags & 0x80 == 0 {
+        0
+    } else {
+        3 << ((flags & 0x07) + 1)
+    }
+}
+
+/// 跳过一串以长度为 0 的子块结尾的数据子块，返回结束位置
+fn skip_sub_blocks(bytes: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let len = *bytes.get(pos)? as usize;
+        pos += 1 + len;
+        if len == 0 {
+            return (pos <= bytes.len()).then_some(pos);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbImage;
+
+    fn sample(format: ImageFormat, width: u32, height: u32) -> Vec<u8> {
+        let img = DynamicImage::new_rgb8(width, height);
+        let mut buf = Vec::new();
+        img.write_to(&mut Cursor::new(&mut buf), format).unwrap();
+        buf
+    }
+
+    #[test]
+    fn test_converts_unsupported_format() {
+        let bmp = sample(ImageFormat::Bmp, 32, 32);
+        let prepared = prepare(&bmp, &UploadConstraints::default()).unwrap();
+        assert_eq!(prepared.format, ImageFormat::Jpeg);
+        assert_eq!(prepared.mime(), "image/jpeg");
+    }
+
+    #[test]
+    fn test_downsizes_to_max_dimension() {
+        let png = sample(ImageFormat::Png, 400, 200);
+        let constraints = UploadConstraints {
+            max_dimension: Some(100),
+            ..Default::default()
+        };
+        let prepared = prepare(&png, &constraints).unwrap();
+        assert_eq!((prepared.width, prepared.height), (100, 50));
+    }
+
+    #[test]
+    fn test_strips_jpeg_exif() {
+        let jpeg = sample(ImageFormat::Jpeg, 16, 16);
+        let exif = [0xFF, 0xE1, 0x00, 0x08, b'E', b'x', b'i', b'f', 0x00, 0x00];
+        let mut with_exif = jpeg[..2].to_vec();
+        with_exif.extend_from_slice(&exif);
+        with_exif.extend_from_slice(&jpeg[2..]);
+
+        let prepared = prepare(&with_exif, &UploadConstraints::default()).unwrap();
+        assert_eq!(prepared.bytes, jpeg);
+    }
+
+    #[test]
+    fn test_detects_letterbox() {
+        // 上下各 10 像素黑边，中间为带噪点的画面
+        let img = RgbImage::from_fn(64, 48, |x, y| {
+            if !(10..38).contains(&y) {
+                Rgb([0, 0, 0])
+            } else {
+                Rgb([(x * 4) as u8, (y * 5) as u8, 128])
+            }
+        });
+        let rect = detect_borders(&DynamicImage::ImageRgb8(img)).unwrap();
+        assert_eq!(
+            rect,
+            CropRect {
+                x: 0,
+                y: 10,
+                width: 64,
+                height: 28
+            }
+        );
+
+        let plain = DynamicImage::new_rgb8(32, 32);
+        assert_eq!(detect_borders(&plain), None);
+    }
+
+    #[test]
+    fn test_rejects_avif() {
+        let avif = b"\x00\x00\x00\x1cftypavif\x00\x00\x00\x00mif1miafMA1B";
+        assert!(matches!(sniff(avif), Err(Error::UnsupportedImage(_))));
+        assert!(matches!(
+            prepare(avif, &UploadConstraints::default()),
+            Err(Error::UnsupportedImage(_))
+        ));
+
+        // 主品牌为 mif1 的 AVIF 不能被当作 HEIC
+        let is = |bytes: &[u8], name: &str| matches!(sniff(bytes), Err(Error::UnsupportedImage(reason)) if reason.contains(name));
+        assert!(is(
+            b"\x00\x00\x00\x1cftypmif1\x00\x00\x00\x00mif1avifmiaf",
+            "AVIF"
+        ));
+        assert!(is(
+            b"\x00\x00\x00\x18ftypmif1\x00\x00\x00\x00mif1heic",
+            "HEIC"
+        ));
+    }
+
+    #[test]
+    fn test_strips_webp_and_gif_metadata() {
+        let webp = sample(ImageFormat::WebP, 16, 16);
+        let mut with_exif = webp.clone();
+        with_exif.extend_from_slice(b"EXIF\x03\x00\x00\x00GPS\x00");
+        let len = (with_exif.len() - 8) as u32;
+        with_exif[4..8].copy_from_slice(&len.to_le_bytes());
+        assert_eq!(strip_metadata(&with_exif, ImageFormat::WebP), webp);
+
+        let gif = sample(ImageFormat::Gif, 16, 16);
+        let comment = b"\x21\xFE\x03GPS\x00";
+        let trailer = gif.len() - 1;
+        let mut with_comment = gif[..trailer].to_vec();
+        with_comment.extend_from_slice(comment);
+        with_comment.extend_from_slice(&gif[trailer..]);
+        assert_eq!(strip_metadata(&with_comment, ImageFormat::Gif), gif);
+    }
+
+    #[test]
+    fn test_rejects_heic() {
+        let heic = b"\x00\x00\x00\x18ftypheic\x00\x00\x00\x00";
+        assert!(matches!(sniff(heic), Err(Error::UnsupportedImage(_))));
+    }
+}
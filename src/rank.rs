@@ -0,0 +1,236 @@
+use crate::merge::MergedResult;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// 没有配置可信度的引擎使用的默认值
+const DEFAULT_RELIABILITY: f32 = 0.6;
+
+/// 不返回相似度的引擎（Google、Yandex 等）命中一次视为的匹配概率
+const UNSCORED_PRIOR: f32 = 0.3;
+
+/// 本地哈希校验在最终置信度中的占比
+const VERIFICATION_WEIGHT: f32 = 0.3;
+
+/// 排名后的条目
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RankedResult {
+    /// 0-1 之间的置信度
+    pub confidence: f32,
+    /// 置信度的计算依据，可直接展示给用户
+    pub explanation: Vec<String>,
+    pub result: MergedResult,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Ranking {
+    /// 置信度最高的条目，即推测的出处
+    pub best: Option<RankedResult>,
+    /// 其余条目，按置信度从高到低排列
+    pub others: Vec<RankedResult>,
+}
+
+/// 综合各引擎相似度、引擎可信度、多引擎一致性和本地哈希校验计算置信度
+///
+/// 每个引擎的命中视为相互独立的证据，概率为 `可信度 × 相似度`，
+/// 合并后的置信度为 `1 - Π(1 - p)`，因此多个引擎指向同一来源时置信度会提高
+#[derive(Debug, Clone)]
+pub struct Ranker {
+    reliability: HashMap<String, f32>,
+    default_reliability: f32,
+    unscored_prior: f32,
+    verification_weight: f32,
+}
+
+impl Default for Ranker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Ranker {
+    pub fn new() -> Self {
+        // 以各引擎的 name() 为键
+        let reliability = [
+            ("SauceNAO", 1.0),
+            ("IQDB", 0.9),
+            ("Soutubot", 0.9),
+            ("ASCII2D", 0.7),
+            ("Yandex", 0.6),
+            ("Google Lens", 0.6),
+            ("Google", 0.5),
+        ]
+        .into_iter()
+        .map(|(engine, weight)| (engine.to_string(), weight))
+        .collect();
+        Self {
+            reliability,
+            default_reliability: DEFAULT_RELIABILITY,
+            unscored_prior: UNSCORED_PRIOR,
+            verification_weight: VERIFICATION_WEIGHT,
+        }
+    }
+
+    /// 设置某个引擎的可信度（0-1）
+    pub fn with_reliability(mut self, engine: &str, reliability: f32) -> Self {
+        self.reliability
+            .insert(engine.to_string(), reliability.clamp(0.0, 1.0));
+        self
+    }
+
+    /// 设置本地哈希校验的占比（0-1），为 0 时忽略校验结果
+    pub fn with_verification_weight(mut self, weight: f32) -> Self {
+        self.verification_weight = weight.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn reliability(&self, engine: &str) -> f32 {
+        self.reliability
+            .get(engine)
+            .copied()
+            .unwrap_or(self.default_reliability)
+    }
+
+    /// 对 `merge::merge` 的结果打分排序
+    pub fn rank(&self, merged: Vec<MergedResult>) -> Ranking {
+        let mut ranked: Vec<RankedResult> = merged
+            .into_iter()
+            .map(|result| self.score(result))
+            .collect();
+        ranked.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+
+        // 置信度为 0 时不算最佳结果，但仍保留在 others 中
+        let best = match ranked.first() {
+            Some(top) if top.confidence > 0.0 => Some(ranked.remove(0)),
+            _ => None,
+        };
+        Ranking {
+            best,
+            others: ranked,
+        }
+    }
+
+    fn score(&self, result: MergedResult) -> RankedResult {
+        let mut explanation = Vec::new();
+
+        // 同一引擎的多条命中只取证据最强的一条
+        let mut evidence: Vec<(&str, f32, Option<f32>)> = Vec::new();
+        for hit in &result.hits {
+            let similarity = hit.result.similarity.map(|s| (s / 100.0).clamp(0.0, 1.0));
+            let p = self.reliability(&hit.engine) * similarity.unwrap_or(self.unscored_prior);
+            match evidence
+                .iter_mut()
+                .find(|(engine, ..)| *engine == hit.engine)
+            {
+                Some(existing) if existing.1 >= p => {}
+                Some(existing) => *existing = (&hit.engine, p, similarity),
+                None => evidence.push((&hit.engine, p, similarity)),
+            }
+        }
+
+        let mut miss = 1.0;
+        for (engine, p, similarity) in &evidence {
+            miss *= 1.0 - p;
+            explanation.push(match similarity {
+                Some(s) => format!(
+                    "{} 相似度 {:.1}%，引擎可信度 {:.2}，证据 {:.2}",
+                    engine,
+                    s * 100.0,
+                    self.reliability(engine),
+                    p
+                ),
+                None => format!("{} 命中但未提供相似度，证据 {:.2}", engine, p),
+            });
+        }
+        let mut confidence = 1.0 - miss;
+
+        if evidence.len() > 1 {
+            let engines: Vec<&str> = evidence.iter().map(|(engine, ..)| *engine).collect();
+            explanation.push(format!(
+                "{} 个引擎指向同一来源：{}",
+                engines.len(),
+                engines.join(", ")
+            ));
+        }
+
+        if let Some(local) = result
+            .local_similarity
+            .filter(|_| self.verification_weight > 0.0)
+        {
+            let local = (local / 100.0).clamp(0.0, 1.0);
+            confidence =
+                confidence * (1.0 - self.verification_weight) + local * self.verification_weight;
+            explanation.push(format!("本地感知哈希相似度 {:.1}%", local * 100.0));
+        }
+
+        if let Some(source) = &result.source {
+            explanation.push(format!("来源识别为 {}", source));
+        }
+        explanation.push(format!("综合置信度 {:.2}", confidence));
+
+        RankedResult {
+            confidence,
+            explanation,
+            result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merge::merge;
+    use crate::types::SearchResult;
+
+    fn result(url: &str, similarity: Option<f32>) -> SearchResult {
+        SearchResult {
+            similarity,
+            ..SearchResult::new(url, "")
+        }
+    }
+
+    #[test]
+    fn test_agreement_beats_single_engine() {
+        let merged = merge([
+            (
+                "SauceNAO",
+                vec![
+                    result("https://www.pixiv.net/artworks/1", Some(80.0)),
+                    result("https://example.com/other", Some(85.0)),
+                ],
+            ),
+            (
+                "IQDB",
+                vec![result("https://www.pixiv.net/artworks/1", Some(75.0))],
+            ),
+            (
+                "Google",
+                vec![result("https://pixiv.net/en/artworks/1", None)],
+            ),
+        ]);
+        let ranking = Ranker::new().rank(merged);
+
+        let best = ranking.best.unwrap();
+        assert_eq!(best.result.url, "https://www.pixiv.net/artworks/1");
+        assert!(best.confidence > ranking.others[0].confidence);
+        assert!(best
+            .explanation
+            .iter()
+            .any(|line| line.contains("3 个引擎")));
+    }
+
+    #[test]
+    fn test_zero_confidence_kept_in_others() {
+        let merged = merge([(
+            "SauceNAO",
+            vec![result("https://www.pixiv.net/artworks/1", Some(0.0))],
+        )]);
+        let ranking = Ranker::new().rank(merged);
+
+        assert!(ranking.best.is_none());
+        assert_eq!(ranking.others.len(), 1);
+        assert_eq!(
+            ranking.others[0].result.url,
+            "https://www.pixiv.net/artworks/1"
+        );
+    }
+}
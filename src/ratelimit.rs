@@ -0,0 +1,249 @@
+use crate::error::{Error, Result};
+use log::debug;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::Instant;
+
+/// 一个引擎的限速规则
+#[derive(Debug, Clone)]
+pub struct RateLimit {
+    /// 滑动窗口限制：每个 `(次数, 时长)` 内最多发出的请求数
+    pub windows: Vec<(usize, Duration)>,
+    /// 相邻两次请求的最小间隔
+    pub min_interval: Option<Duration>,
+    /// 同时进行中的请求数上限
+    pub max_concurrent: Option<usize>,
+    /// 排队的最长时间，超过时返回 `Error::RateLimit`；默认为 `None`，一直等到可以发出请求
+    ///
+    /// SauceNAO 的 24 小时额度用完后可能需要等待数小时，不希望被挂起的调用方可以通过
+    /// `with_max_wait` 设置上限
+    pub max_wait: Option<Duration>,
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        Self {
+            windows: Vec::new(),
+            min_interval: Some(Duration::from_secs(1)),
+            max_concurrent: Some(2),
+            max_wait: None,
+        }
+    }
+}
+
+impl RateLimit {
+    /// 不做任何限制
+    pub fn unlimited() -> Self {
+        Self {
+            windows: Vec::new(),
+            min_interval: None,
+            max_concurrent: None,
+            max_wait: None,
+        }
+    }
+
+    /// 各引擎的默认规则，以 `ImageSearch::name()` 为键
+    pub fn for_engine(engine: &str) -> Self {
+        match engine {
+            // SauceNAO 免费账户的限制：30 秒 4 次，24 小时 100 次
+            "SauceNAO" => Self {
+                windows: vec![
+                    (4, Duration::from_secs(30)),
+                    (100, Duration::from_secs(24 * 60 * 60)),
+                ],
+                min_interval: None,
+                max_concurrent: Some(1),
+                ..Default::default()
+            },
+            // ASCII2D 对频繁访问非常敏感，一次搜索本身就包含三个请求
+            "ASCII2D" => Self {
+                min_interval: Some(Duration::from_secs(2)),
+                max_concurrent: Some(1),
+                ..Default::default()
+            },
+            "Google" | "Google Lens" | "Yandex" => Self {
+                min_interval: Some(Duration::from_secs(2)),
+                ..Default::default()
+            },
+            // 校验时下载的缩略图分散在各个图床，并发数已由 `verify` 控制
+            crate::verify::THUMBNAIL => Self::unlimited(),
+            _ => Self::default(),
+        }
+    }
+
+    pub fn with_window(mut self, max: usize, window: Duration) -> Self {
+        self.windows.push((max, window));
+        self
+    }
+
+    pub fn with_min_interval(mut self, interval: Duration) -> Self {
+        self.min_interval = Some(interval);
+        self
+    }
+
+    pub fn with_max_concurrent(mut self, max: usize) -> Self {
+        self.max_concurrent = Some(max);
+        self
+    }
+
+    pub fn with_max_wait(mut self, max_wait: Option<Duration>) -> Self {
+        self.max_wait = max_wait;
+        self
+    }
+}
+
+/// 按规则排队放行请求，等待按到达顺序进行
+#[derive(Debug)]
+pub struct RateLimiter {
+    name: &'static str,
+    limit: RateLimit,
+    // 发出请求的时间，只保留最长窗口内的记录
+    history: tokio::sync::Mutex<VecDeque<Instant>>,
+    semaphore: Option<Arc<Semaphore>>,
+}
+
+/// 持有期间占用一个并发名额，网络层会一直持有到响应正文读取完毕
+#[derive(Debug)]
+pub struct RatePermit {
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+impl RateLimiter {
+    pub fn new(name: &'static str, limit: RateLimit) -> Self {
+        Self {
+            name,
+            semaphore: limit
+                .max_concurrent
+                .map(|max| Arc::new(Semaphore::new(max.max(1)))),
+            limit,
+            history: tokio::sync::Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub fn limit(&self) -> &RateLimit {
+        &self.limit
+    }
+
+    /// 等待直到可以发出下一个请求；需要等待的时间超过 `max_wait` 时返回 `Error::RateLimit`
+    pub async fn acquire(&self) -> Result<RatePermit> {
+        let permit = match &self.semaphore {
+            Some(semaphore) => Some(
+                Arc::clone(semaphore)
+                    .acquire_owned()
+                    .await
+                    .map_err(|_| Error::RateLimit { engine: self.name })?,
+            ),
+            None => None,
+        };
+
+        // 持锁等待，后来的请求在锁上排队，保证先到先发
+        let mut history = self.history.lock().await;
+        let now = Instant::now();
+        let wait = self.wait_time(&history, now);
+        if !wait.is_zero() {
+            // 窗口上限为 0 时永远不会放行
+            if wait == Duration::MAX || self.limit.max_wait.is_some_and(|max| wait > max) {
+                debug!("{} 需要等待 {:?}，超过上限", self.name, wait);
+                return Err(Error::RateLimit { engine: self.name });
+            }
+            debug!("{} 限速，等待 {:?}", self.name, wait);
+            tokio::time::sleep(wait).await;
+        }
+
+        let now = Instant::now();
+        history.push_back(now);
+        let longest = self.limit.windows.iter().map(|(_, w)| *w).max();
+        while let Some(&oldest) = history.front() {
+            let keep = longest.is_some_and(|w| now.duration_since(oldest) < w);
+            if keep || history.len() == 1 {
+                break;
+            }
+            history.pop_front();
+        }
+
+        Ok(RatePermit { _permit: permit })
+    }
+
+    fn wait_time(&self, history: &VecDeque<Instant>, now: Instant) -> Duration {
+        let mut ready = now;
+        if let (Some(interval), Some(&last)) = (self.limit.min_interval, history.back()) {
+            ready = ready.max(last + interval);
+        }
+        for &(max, window) in &self.limit.windows {
+            if max == 0 {
+                return Duration::MAX;
+            }
+            // 窗口内已有 max 次请求时，要等到其中最早的一次滑出窗口
+            let in_window: Vec<&Instant> = history
+                .iter()
+                .filter(|&&t| now.duration_since(t) < window)
+                .collect();
+            if in_window.len() >= max {
+                ready = ready.max(*in_window[in_window.len() - max] + window);
+            }
+        }
+        ready.duration_since(now)
+    }
+}
+
+fn registry() -> &'static Mutex<HashMap<String, Arc<RateLimiter>>> {
+    static REGISTRY: OnceLock<Mutex<HashMap<String, Arc<RateLimiter>>>> = OnceLock::new();
+    REGISTRY.get_or_init(Default::default)
+}
+
+/// 获取某个引擎的限速器，所有同名引擎实例共享同一个
+pub fn limiter(engine: &'static str) -> Arc<RateLimiter> {
+    let mut registry = registry().lock().unwrap_or_else(|e| e.into_inner());
+    Arc::clone(
+        registry
+            .entry(engine.to_string())
+            .or_insert_with(|| Arc::new(RateLimiter::new(engine, RateLimit::for_engine(engine)))),
+    )
+}
+
+/// 修改某个引擎的限速规则，例如 SauceNAO 付费账户可以放宽限制
+///
+/// 已经在排队的请求仍按旧规则执行
+pub fn set_rate_limit(engine: &'static str, limit: RateLimit) {
+    let mut registry = registry().lock().unwrap_or_else(|e| e.into_inner());
+    registry.insert(
+        engine.to_string(),
+        Arc::new(RateLimiter::new(engine, limit)),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_min_interval() {
+        let limiter = RateLimiter::new(
+            "test",
+            RateLimit::unlimited().with_min_interval(Duration::from_millis(50)),
+        );
+        let start = Instant::now();
+        for _ in 0..3 {
+            limiter.acquire().await.unwrap();
+        }
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn test_window_exceeding_max_wait() {
+        let limiter = RateLimiter::new(
+            "test",
+            RateLimit::unlimited()
+                .with_window(2, Duration::from_secs(60))
+                .with_max_wait(Some(Duration::from_secs(1))),
+        );
+        limiter.acquire().await.unwrap();
+        limiter.acquire().await.unwrap();
+        assert!(matches!(
+            limiter.acquire().await,
+            Err(Error::RateLimit { engine: "test" })
+        ));
+    }
+}
@@ -0,0 +1,298 @@
+use crate::engines::ImageSearch;
+use crate::error::{Error, Result};
+use crate::preprocess;
+use crate::types::{CropRect, SearchOptions, SearchResult};
+use image::{DynamicImage, GenericImageView};
+use log::debug;
+
+/// 分格检测时，单个分格在每个方向上至少占原图的比例
+const MIN_PANEL_RATIO: f32 = 0.08;
+
+/// 只搜索图片的一部分
+#[derive(Debug, Clone)]
+pub enum Region {
+    /// 指定的矩形区域
+    Rect(CropRect),
+    /// 多个矩形区域，例如从界面上框选的几个人物
+    Rects(Vec<CropRect>),
+    /// 均匀切分为 `rows` 行 `cols` 列，例如 2x2 拼图
+    Grid { rows: u32, cols: u32 },
+    /// 按分格之间的留白自动切分漫画页
+    Panels,
+}
+
+/// 单个区域的搜索结果
+#[derive(Debug)]
+pub struct RegionResult {
+    pub rect: CropRect,
+    /// `(结果页链接, 结果)`，某个区域失败不影响其他区域
+    pub result: Result<(String, Vec<SearchResult>)>,
+}
+
+/// 计算需要搜索的区域，超出图片范围的部分会被截掉，空区域会被丢弃
+pub fn regions(img: &DynamicImage, region: &Region) -> Vec<CropRect> {
+    let (width, height) = img.dimensions();
+    let rects = match region {
+        Region::Rect(rect) => vec![*rect],
+        Region::Rects(rects) => rects.clone(),
+        Region::Grid { rows, cols } => grid(width, height, *rows, *cols),
+        Region::Panels => detect_panels(img),
+    };
+    rects
+        .into_iter()
+        .filter_map(|rect| clamp(rect, width, height))
+        .collect()
+}
+
+/// 均匀切分，余下的像素并入最后一行（列）
+pub fn grid(width: u32, height: u32, rows: u32, cols: u32) -> Vec<CropRect> {
+    if rows == 0 || cols == 0 {
+        return Vec::new();
+    }
+    let (cell_w, cell_h) = (width / cols, height / rows);
+    let mut rects = Vec::with_capacity((rows * cols) as usize);
+    for row in 0..rows {
+        for col in 0..cols {
+            let (x, y) = (col * cell_w, row * cell_h);
+            rects.push(CropRect {
+                x,
+                y,
+                width: if col + 1 == cols { width - x } else { cell_w },
+                height: if row + 1 == rows { height - y } else { cell_h },
+            });
+        }
+    }
+    rects
+}
+
+/// 按纯色留白切分分格：先按整行留白切成横条，再在每个横条内按整列留白切分
+///
+/// 结果按从上到下、从左到右排列；找不到留白时返回整张图片
+pub fn detect_panels(img: &DynamicImage) -> Vec<CropRect> {
+    let rgb = &img.to_rgb8();
+    let (width, height) = rgb.dimensions();
+    let whole = CropRect {
+        x: 0,
+        y: 0,
+        width,
+        height,
+    };
+    // 留白颜色取最外圈像素的中位数
+    let frame = (0..width)
+        .map(|x| *rgb.get_pixel(x, 0))
+        .chain((0..height).map(|y| *rgb.get_pixel(0, y)));
+    let Some(gutter) = preprocess::median_color(frame) else {
+        return Vec::new();
+    };
+
+    let min_height = ((height as f32 * MIN_PANEL_RATIO) as u32).max(1);
+    let min_width = ((width as f32 * MIN_PANEL_RATIO) as u32).max(1);
+
+    let strips = segments(height, min_height, |y| {
+        preprocess::is_uniform((0..width).map(|x| *rgb.get_pixel(x, y)), gutter)
+    });
+
+    let mut panels = Vec::new();
+    for (y, strip_height) in strips {
+        let columns = segments(width, min_width, |x| {
+            preprocess::is_uniform((y..y + strip_height).map(|y| *rgb.get_pixel(x, y)), gutter)
+        });
+        panels.extend(columns.into_iter().map(|(x, panel_width)| CropRect {
+            x,
+            y,
+            width: panel_width,
+            height: strip_height,
+        }));
+    }
+
+    if panels.is_empty() {
+        vec![whole]
+    } else {
+        panels
+    }
+}
+
+/// 找出不是留白的连续区间 `(起点, 长度)`，太窄的区间视为噪点丢弃
+fn segments(len: u32, min_len: u32, is_gutter: impl Fn(u32) -> bool) -> Vec<(u32, u32)> {
+    let mut segments = Vec::new();
+    let mut start = None;
+    for i in 0..=len {
+        let gutter = i == len || is_gutter(i);
+        match (start, gutter) {
+            (None, false) => start = Some(i),
+            (Some(s), true) => {
+                if i - s >= min_len {
+                    segments.push((s, i - s));
+                }
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    segments
+}
+
+fn clamp(rect: CropRect, width: u32, height: u32) -> Option<CropRect> {
+    if rect.x >= width || rect.y >= height {
+        return None;
+    }
+    let rect = CropRect {
+        width: rect.width.min(width - rect.x),
+        height: rect.height.min(height - rect.y),
+        ..rect
+    };
+    (rect.width > 0 && rect.height > 0).then_some(rect)
+}
+
+/// 在本地裁出每个区域并分别搜索，结果按区域分组返回
+///
+/// 只有图片无法解码或没有有效区域时整体失败，单个区域的错误记录在对应的 `RegionResult` 中
+pub async fn search_regions<S>(
+    engine: &S,
+    bytes: &[u8],
+    region: &Region,
+    options: &SearchOptions,
+) -> Result<Vec<RegionResult>>
+where
+    S: ImageSearch + ?Sized,
+{
+    let img = preprocess::decode(bytes)?;
+    let rects = regions(&img, region);
+    if rects.is_empty() {
+        return Err(Error::InvalidArgument("裁剪区域不在图片范围内".to_string()));
+    }
+
+    let mut grouped = Vec::with_capacity(rects.len());
+    // 依次搜索，避免同时向同一个引擎发出大量请求
+    for rect in rects {
+        debug!("{} 搜索区域 {:?}", engine.name(), rect);
+        let cropped = img.crop_imm(rect.x, rect.y, rect.width, rect.height);
+        let result = match preprocess::encode_png(&cropped) {
+            Ok(png) => engine.search_bytes(&png, options).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = &result {
+            debug!("{} 区域 {:?} 搜索失败: {}", engine.name(), rect, e);
+        }
+        grouped.push(RegionResult { rect, result });
+    }
+    Ok(grouped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use image::{Rgb, RgbImage};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// 第二次搜索失败的引擎
+    struct FailsSecond(AtomicUsize);
+
+    #[async_trait]
+    impl ImageSearch for FailsSecond {
+        fn name(&self) -> &'static str {
+            "Region Test"
+        }
+
+        async fn search_url(
+            &self,
+            url: &str,
+            _options: &SearchOptions,
+        ) -> Result<(String, Vec<SearchResult>)> {
+            match self.0.fetch_add(1, Ordering::SeqCst) {
+                1 => Err(Error::Engine("second region failed".to_string())),
+                _ => Ok((url.to_string(), Vec::new())),
+            }
+        }
+
+        async fn search_bytes(
+            &self,
+            _bytes: &[u8],
+            options: &SearchOptions,
+        ) -> Result<(String, Vec<SearchResult>)> {
+            self.search_url("page", options).await
+        }
+    }
+
+    #[test]
+    fn test_grid() {
+        let rects = grid(101, 50, 2, 2);
+        assert_eq!(rects.len(), 4);
+        assert_eq!(
+            rects[3],
+            CropRect {
+                x: 50,
+                y: 25,
+                width: 51,
+                height: 25
+            }
+        );
+    }
+
+    #[test]
+    fn test_detect_panels() {
+        // 白色留白分隔的三个分格：上方一个通栏，下方左右各一个
+        let img = RgbImage::from_fn(100, 100, |x, y| {
+            let top = (5..45).contains(&y) && (5..95).contains(&x);
+            let bottom_left = (55..95).contains(&y) && (5..45).contains(&x);
+            let bottom_right = (55..95).contains(&y) && (55..95).contains(&x);
+            if top || bottom_left || bottom_right {
+                Rgb([(x * 2) as u8, (y * 2) as u8, 64])
+            } else {
+                Rgb([255, 255, 255])
+            }
+        });
+        let panels = detect_panels(&DynamicImage::ImageRgb8(img));
+        assert_eq!(
+            panels,
+            vec![
+                CropRect {
+                    x: 5,
+                    y: 5,
+                    width: 90,
+                    height: 40
+                },
+                CropRect {
+                    x: 5,
+                    y: 55,
+                    width: 40,
+                    height: 40
+                },
+                CropRect {
+                    x: 55,
+                    y: 55,
+                    width: 40,
+                    height: 40
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_region_errors_are_kept_per_region() {
+        let png = preprocess::encode_png(&DynamicImage::new_rgb8(30, 10)).unwrap();
+        let engine = FailsSecond(AtomicUsize::new(0));
+        let options = SearchOptions::default();
+
+        let grid = Region::Grid { rows: 1, cols: 3 };
+        let grouped = search_regions(&engine, &png, &grid, &options)
+            .await
+            .unwrap();
+        assert_eq!(grouped.len(), 3);
+        assert!(grouped[0].result.is_ok());
+        assert!(matches!(grouped[1].result, Err(Error::Engine(_))));
+        assert!(grouped[2].result.is_ok());
+
+        let outside = Region::Rect(CropRect {
+            x: 50,
+            y: 0,
+            width: 10,
+            height: 10,
+        });
+        assert!(matches!(
+            search_regions(&engine, &png, &outside, &options).await,
+            Err(Error::InvalidArgument(_))
+        ));
+    }
+}
@@ -0,0 +1,268 @@
+use crate::error::Result;
+use log::debug;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::StatusCode;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+/// 一个引擎的重试规则
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// 总尝试次数，包含第一次请求，为 1 时不重试
+    pub max_attempts: u32,
+    /// 第一次重试前的等待时间，之后每次翻倍
+    pub base_delay: Duration,
+    /// 单次等待的上限
+    pub max_delay: Duration,
+    /// 在退避时间上加随机抖动，避免多个请求同时重试
+    pub jitter: bool,
+    /// 需要重试的 HTTP 状态码
+    pub retry_statuses: Vec<u16>,
+    /// `Retry-After` 要求等待的时间超过该值时放弃重试，直接返回响应
+    pub max_retry_after: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(10),
+            jitter: true,
+            retry_statuses: vec![429, 500, 502, 503, 504],
+            max_retry_after: Duration::from_secs(60),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// 只发送一次
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    /// 各引擎的默认规则，以 `ImageSearch::name()` 为键
+    pub fn for_engine(engine: &str) -> Self {
+        match engine {
+            // SauceNAO 的 429 通常是次数用尽，重试只会继续消耗额度
+            "SauceNAO" => Self {
+                retry_statuses: vec![500, 502, 503, 504],
+                ..Default::default()
+            },
+            // Google 的 429 伴随人机验证页面，重试无济于事
+            "Google" | "Google Lens" => Self {
+                retry_statuses: vec![500, 502, 503, 504],
+                ..Default::default()
+            },
+            _ => Self::default(),
+        }
+    }
+
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    pub fn with_backoff(mut self, base_delay: Duration, max_delay: Duration) -> Self {
+        self.base_delay = base_delay;
+        self.max_delay = max_delay;
+        self
+    }
+
+    pub fn with_jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    pub fn with_retry_statuses(mut self, statuses: Vec<u16>) -> Self {
+        self.retry_statuses = statuses;
+        self
+    }
+
+    pub fn retries_status(&self, status: StatusCode) -> bool {
+        self.retry_statuses.contains(&status.as_u16())
+    }
+
+    /// 第 `attempt` 次尝试失败后的等待时间（指数退避）
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        let delay = self.base_delay.saturating_mul(factor).min(self.max_delay);
+        if self.jitter {
+            // 在 [delay/2, delay] 之间均匀随机，随机数来自线程本地的 rand::thread_rng
+            let half = delay / 2;
+            half + half.mul_f64(rand::random::<f64>())
+        } else {
+            delay
+        }
+    }
+
+    /// 根据响应决定等待时间：优先使用 `Retry-After`，超过上限时返回 `None` 表示放弃
+    pub fn delay_for(&self, attempt: u32, headers: &HeaderMap) -> Option<Duration> {
+        match retry_after(headers) {
+            Some(wait) if wait > self.max_retry_after => None,
+            Some(wait) => Some(wait),
+            None => Some(self.backoff(attempt)),
+        }
+    }
+}
+
+/// 解析 `Retry-After`，支持秒数与 HTTP 日期两种格式
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let wait = date.signed_duration_since(chrono::Utc::now());
+    Some(wait.to_std().unwrap_or(Duration::ZERO))
+}
+
+/// 连接失败、超时等与请求内容无关的错误可以重试
+pub fn is_transient(error: &reqwest::Error) -> bool {
+    error.is_timeout() || error.is_connect() || error.is_request()
+}
+
+/// 按规则重试整个操作，用于响应内容才能判断是否需要重试的场景，例如 SauceNAO 的 `status` 字段
+///
+/// 是否重试由 `Error::is_retryable` 决定；网络错误与 HTTP 状态码在发送时已经按同一规则
+/// 重试过，这里不再重复
+pub async fn retry<T, F, Fut>(policy: &RetryPolicy, mut operation: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut attempt = 1;
+    loop {
+        match operation().await {
+            Err(e) if attempt < policy.max_attempts && retries_content(&e) => {
+                let delay = policy.backoff(attempt);
+                debug!("第 {} 次尝试失败: {}，{:?} 后重试", attempt, e, delay);
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+fn retries_content(error: &crate::Error) -> bool {
+    use crate::Error;
+    error.is_retryable()
+        && !matches!(
+            error,
+            Error::Request(_) | Error::Timeout { .. } | Error::HttpStatus { .. }
+        )
+}
+
+fn registry() -> &'static Mutex<HashMap<String, Arc<RetryPolicy>>> {
+    static REGISTRY: OnceLock<Mutex<HashMap<String, Arc<RetryPolicy>>>> = OnceLock::new();
+    REGISTRY.get_or_init(Default::default)
+}
+
+/// 获取某个引擎的重试规则
+pub fn policy(engine: &str) -> Arc<RetryPolicy> {
+    let mut registry = registry().lock().unwrap_or_else(|e| e.into_inner());
+    Arc::clone(
+        registry
+            .entry(engine.to_string())
+            .or_insert_with(|| Arc::new(RetryPolicy::for_engine(engine))),
+    )
+}
+
+/// 修改某个引擎的重试规则，对之后发出的请求生效
+pub fn set_retry_policy(engine: &str, policy: RetryPolicy) {
+    let mut registry = registry().lock().unwrap_or_else(|e| e.into_inner());
+    registry.insert(engine.to_string(), Arc::new(policy));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[test]
+    fn test_backoff_and_retry_after() {
+        let policy = RetryPolicy::default().with_jitter(false);
+        assert_eq!(policy.backoff(1), Duration::from_millis(500));
+        assert_eq!(policy.backoff(3), Duration::from_secs(2));
+        assert_eq!(policy.backoff(10), Duration::from_secs(10));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, "7".parse().unwrap());
+        assert_eq!(policy.delay_for(1, &headers), Some(Duration::from_secs(7)));
+        headers.insert(RETRY_AFTER, "3600".parse().unwrap());
+        assert_eq!(policy.delay_for(1, &headers), None);
+        headers.insert(
+            RETRY_AFTER,
+            "Wed, 21 Oct 2015 07:28:00 GMT".parse().unwrap(),
+        );
+        assert_eq!(retry_after(&headers), Some(Duration::ZERO));
+    }
+
+    #[tokio::test]
+    async fn test_retry_only_retryable_errors() {
+        let policy = RetryPolicy::default().with_backoff(Duration::ZERO, Duration::ZERO);
+        let calls = AtomicU32::new(0);
+        let result: Result<()> = retry(&policy, || async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Err(Error::Api {
+                engine: "SauceNAO",
+                code: 1,
+                message: "server busy".to_string(),
+            })
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        // API key 无效不重试；HTTP 状态码已经在发送时重试过
+        for error in [
+            Error::InvalidApiKey { engine: "SauceNAO" },
+            Error::HttpStatus {
+                engine: "SauceNAO",
+                code: 503,
+                body_snippet: String::new(),
+            },
+        ] {
+            calls.store(0, Ordering::SeqCst);
+            let error = std::sync::Mutex::new(Some(error));
+            let _ = retry(&policy, || async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Err::<(), _>(error.lock().unwrap().take().unwrap())
+            })
+            .await;
+            assert_eq!(calls.load(Ordering::SeqCst), 1);
+        }
+    }
+
+    #[test]
+    fn test_is_retryable_follows_policy() {
+        let status = |engine, code| Error::HttpStatus {
+            engine,
+            code,
+            body_snippet: String::new(),
+        };
+        assert!(status("Yandex", 429).is_retryable());
+        assert!(status("SauceNAO", 503).is_retryable());
+        assert!(!status("SauceNAO", 429).is_retryable());
+        assert!(!status("Yandex", 404).is_retryable());
+
+        assert!(Error::RateLimit { engine: "ASCII2D" }.is_retryable());
+        assert!(!Error::RateLimit { engine: "SauceNAO" }.is_retryable());
+
+        let api = |engine, code| Error::Api {
+            engine,
+            code,
+            message: String::new(),
+        };
+        assert!(api("SauceNAO", 1).is_retryable());
+        assert!(!api("SauceNAO", -1).is_retryable());
+        assert!(!api("Soutubot", 1).is_retryable());
+    }
+}
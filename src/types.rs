@@ -1,26 +1,100 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fmt;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct SearchResult {
     pub title: Option<String>,
     pub url: String,
     pub thumbnail: Option<String>,
     pub similarity: Option<f32>,
+    /// 本地比较缩略图感知哈希得到的相似度（0-100），开启 `SearchOptions::verify` 后填入
+    #[serde(default)]
+    pub local_similarity: Option<f32>,
     pub source: String,
     pub index: Option<String>,
     pub additional_info: Option<AdditionalInfo>,
 }
 
+#[cfg(test)]
+impl SearchResult {
+    /// 只有链接与来源的结果，其余字段为空
+    pub(crate) fn new(url: impl Into<String>, source: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            source: source.into(),
+            ..Default::default()
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct AdditionalInfo {
     pub author: Option<String>,
     pub author_url: Option<String>,
     pub source_url: Option<String>,
+    /// 结果所在站点的名称或域名，例如 `pixiv.net`，不是具体页面的链接
+    #[serde(default)]
+    pub site: Option<String>,
     pub created_at: Option<String>,
     pub tags: Vec<String>,
     pub size: Option<(u32, u32)>,
     pub ext_urls: Vec<String>,
+    /// 结果页上的摘要或描述文字
+    pub snippet: Option<String>,
+    pub rating: Option<Rating>,
+    /// 结果所在图站的帖子 id，例如 IQDB 结果链接中的 Danbooru 帖子编号
+    #[serde(default)]
+    pub post_id: Option<String>,
+    /// 引擎给出的匹配等级，目前只有 IQDB 提供
+    #[serde(default)]
+    pub match_tier: Option<MatchTier>,
+    /// 本子的语言代码，例如 `cn`、`jp`，目前只有 Soutubot 提供
+    #[serde(default)]
+    pub language: Option<String>,
+    /// 命中的具体页面，而不仅是画廊首页，目前只有 Soutubot 提供
+    #[serde(default)]
+    pub page_url: Option<String>,
+    /// 引擎特有的附加信息，例如 IQDB 的 `score`
+    #[serde(default)]
+    pub extra: BTreeMap<String, String>,
+}
+
+/// IQDB 结果表格表头中的匹配等级
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MatchTier {
+    /// "Best match"
+    Best,
+    /// "Additional match"，同一图片在其他图站的结果
+    Additional,
+    /// "Possible match"，相似度较低的候选
+    Possible,
+}
+
+/// 内容分级，IQDB 的 `[Ero]` 对应 `Questionable`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Rating {
+    Safe,
+    Questionable,
+    Explicit,
+}
+
+/// 图片中的矩形区域（像素）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CropRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// `ImageSearch::search_image` 的结果，附带实际上传前做过的裁剪
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchResponse {
+    pub page_url: String,
+    pub results: Vec<SearchResult>,
+    /// 自动裁边时保留下来的区域，相对于原图
+    pub crop: Option<CropRect>,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -59,7 +133,22 @@ pub struct SearchOptions {
     pub proxy: Option<String>,
     pub timeout: Option<u64>,
     pub min_similarity: Option<f32>,
+    /// 隐藏成人内容：SauceNAO 由服务端按 `hide` 参数过滤，IQDB 与 Soutubot 按结果的分级过滤，
+    /// 其他引擎不提供分级，会忽略该选项
     pub hide_explicit: bool,
+    /// 上传前裁掉纯色边框与黑边，仅对 `ImageSearch::search_image` 生效
+    pub auto_crop: bool,
+    /// 下载结果缩略图并计算本地相似度，仅对 `ImageSearch::search_image` 生效
+    pub verify: bool,
+}
+
+impl SearchOptions {
+    /// 开启 `hide_explicit` 时排除分级为 explicit 的结果
+    pub(crate) fn allows(&self, result: &SearchResult) -> bool {
+        !self.hide_explicit
+            || result.additional_info.as_ref().and_then(|info| info.rating)
+                != Some(Rating::Explicit)
+    }
 }
 
 impl Default for SearchOptions {
@@ -69,6 +158,8 @@ impl Default for SearchOptions {
             timeout: None,
             min_similarity: Some(50.0),
             hide_explicit: false,
+            auto_crop: false,
+            verify: false,
         }
     }
 }
@@ -3,7 +3,6 @@ use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
 use std::io::Cursor;
 use std::path::Path;
 
-
 pub async fn image_to_base64<P: AsRef<Path>>(path: P) -> Result<String> {
     let img = image::open(path)?;
     // 转换为 RGB 并调整大小到 250x250
@@ -24,8 +23,12 @@ pub fn base64_to_bytes(base64: &str) -> Result<Vec<u8>> {
     Ok(BASE64.decode(base64)?)
 }
 
+/// 百分号编码，空格编码为 `%20`，可用于路径与查询参数
 pub fn url_encode(s: &str) -> String {
-    url::form_urlencoded::byte_serialize(s.as_bytes()).collect()
+    // form_urlencoded 会把空格编码为 `+`，原本的 `+` 已经被编码为 `%2B`，替换不会误伤
+    url::form_urlencoded::byte_serialize(s.as_bytes())
+        .collect::<String>()
+        .replace('+', "%20")
 }
 
 pub fn html_unescape(s: &str) -> String {
@@ -54,6 +57,7 @@ mod tests {
     fn test_url_encode() {
         assert_eq!(url_encode("hello world"), "hello%20world");
         assert_eq!(url_encode("你好"), "%E4%BD%A0%E5%A5%BD");
+        assert_eq!(url_encode("a+b c"), "a%2Bb%20c");
     }
 
     #[test]
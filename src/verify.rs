@@ -0,0 +1,203 @@
+use crate::error::Result;
+use crate::network::Network;
+use crate::preprocess;
+use crate::types::{SearchOptions, SearchResult};
+use base64::{engine::general_purpose, Engine as _};
+use futures::{stream, StreamExt};
+use image::imageops::FilterType;
+use image::DynamicImage;
+use log::debug;
+use std::f64::consts::PI;
+
+/// 缩略图一般只有几十 KB，限制大小以免误下载原图
+const MAX_THUMBNAIL_SIZE: usize = 5 * 1024 * 1024;
+
+/// 同时下载的缩略图数量
+const CONCURRENCY: usize = 4;
+
+/// 缩略图下载使用的限速、重试与磁带的键，与搜索引擎的额度分开计算
+pub const THUMBNAIL: &str = "thumbnail";
+
+/// pHash 先缩放到的边长
+const DCT_SIZE: usize = 32;
+
+/// 一张图片的三种感知哈希，均为 64 位
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageHash {
+    /// 均值哈希：8x8 灰度图中高于平均值的像素
+    pub ahash: u64,
+    /// 差异哈希：9x8 灰度图中每个像素是否比右侧像素亮
+    pub dhash: u64,
+    /// DCT 哈希：32x32 灰度图的低频系数是否高于中位数
+    pub phash: u64,
+}
+
+impl ImageHash {
+    pub fn from_image(img: &DynamicImage) -> Self {
+        Self {
+            ahash: ahash(img),
+            dhash: dhash(img),
+            phash: phash(img),
+        }
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        Ok(Self::from_image(&preprocess::decode(bytes)?))
+    }
+
+    /// 三种哈希相似度的平均值，范围 0-100，与引擎返回的相似度同一量纲
+    pub fn similarity(&self, other: &ImageHash) -> f32 {
+        let score = |a: u64, b: u64| 1.0 - (a ^ b).count_ones() as f32 / 64.0;
+        let total = score(self.ahash, other.ahash)
+            + score(self.dhash, other.dhash)
+            + score(self.phash, other.phash);
+        total / 3.0 * 100.0
+    }
+}
+
+/// 下载每个结果的缩略图，与查询图片比较后填入 `local_similarity`
+///
+/// 下载按 `THUMBNAIL` 限速，不占用搜索引擎的额度；没有缩略图或缩略图无法下载、解码的结果保持 `None`
+pub async fn verify_results(
+    query: &[u8],
+    results: &mut [SearchResult],
+    options: &SearchOptions,
+) -> Result<()> {
+    let query_hash = ImageHash::from_bytes(query)?;
+    let network = Network::from_options(options)?.for_engine(THUMBNAIL);
+
+    let thumbnails: Vec<Option<String>> = results.iter().map(|r| r.thumbnail.clone()).collect();
+    let scores: Vec<Option<f32>> = stream::iter(thumbnails)
+        .map(|thumbnail| score(&network, query_hash, thumbnail))
+        .buffered(CONCURRENCY)
+        .collect()
+        .await;
+
+    for (result, score) in results.iter_mut().zip(scores) {
+        result.local_similarity = score;
+    }
+    Ok(())
+}
+
+async fn score(network: &Network, query: ImageHash, thumbnail: Option<String>) -> Option<f32> {
+    let thumbnail = thumbnail?;
+    match thumbnail_hash(network, &thumbnail).await {
+        Ok(hash) => Some(query.similarity(&hash)),
+        Err(e) => {
+            debug!("缩略图校验失败 {}: {}", thumbnail, e);
+            None
+        }
+    }
+}
+
+async fn thumbnail_hash(network: &Network, thumbnail: &str) -> Result<ImageHash> {
+    // Google Lens 等引擎会直接内嵌 base64 缩略图
+    let bytes = match thumbnail
+        .strip_prefix("data:")
+        .and_then(|data| data.split_once(";base64,"))
+    {
+        Some((_, data)) => general_purpose::STANDARD.decode(data)?,
+        None => network
+            .download(thumbnail, MAX_THUMBNAIL_SIZE)
+            .await?
+            .to_vec(),
+    };
+    ImageHash::from_bytes(&bytes)
+}
+
+fn grayscale(img: &DynamicImage, width: u32, height: u32) -> Vec<f64> {
+    img.resize_exact(width, height, FilterType::Triangle)
+        .to_luma8()
+        .pixels()
+        .map(|p| p.0[0] as f64)
+        .collect()
+}
+
+fn bits(values: impl Iterator<Item = bool>) -> u64 {
+    values
+        .enumerate()
+        .fold(0, |hash, (i, set)| if set { hash | 1 << i } else { hash })
+}
+
+fn ahash(img: &DynamicImage) -> u64 {
+    let pixels = grayscale(img, 8, 8);
+    let mean = pixels.iter().sum::<f64>() / pixels.len() as f64;
+    bits(pixels.iter().map(|&p| p > mean))
+}
+
+fn dhash(img: &DynamicImage) -> u64 {
+    let pixels = grayscale(img, 9, 8);
+    bits(
+        pixels
+            .chunks(9)
+            .flat_map(|row| row.windows(2).map(|w| w[0] > w[1])),
+    )
+}
+
+fn phash(img: &DynamicImage) -> u64 {
+    let pixels = grayscale(img, DCT_SIZE as u32, DCT_SIZE as u32);
+    let dct = dct_2d(&pixels);
+
+    // 只取左上角 8x8 的低频部分，直流分量不参与中位数计算
+    let low: Vec<f64> = (0..8)
+        .flat_map(|y| dct[y * DCT_SIZE..y * DCT_SIZE + 8].iter().copied())
+        .collect();
+    let mut sorted = low[1..].to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let median = sorted[sorted.len() / 2];
+    bits(low.iter().map(|&v| v > median))
+}
+
+/// 朴素的二维 DCT-II，先对行再对列各做一次一维变换
+fn dct_2d(pixels: &[f64]) -> Vec<f64> {
+    let n = DCT_SIZE;
+    let cos: Vec<f64> = (0..n * n)
+        .map(|i| {
+            let (k, x) = (i / n, i % n);
+            ((2 * x + 1) as f64 * k as f64 * PI / (2 * n) as f64).cos()
+        })
+        .collect();
+    let dct_1d = |input: &mut dyn Iterator<Item = f64>| -> Vec<f64> {
+        let input: Vec<f64> = input.collect();
+        (0..n)
+            .map(|k| (0..n).map(|x| input[x] * cos[k * n + x]).sum())
+            .collect()
+    };
+
+    let rows: Vec<f64> = pixels
+        .chunks(n)
+        .flat_map(|row| dct_1d(&mut row.iter().copied()))
+        .collect();
+    let mut out = vec![0.0; n * n];
+    for x in 0..n {
+        let column = dct_1d(&mut (0..n).map(|y| rows[y * n + x]));
+        for (y, value) in column.into_iter().enumerate() {
+            out[y * n + x] = value;
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    fn gradient(width: u32, height: u32, flip: bool) -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, y| {
+            let v = ((x * 255 / width) ^ (y * 255 / height)) as u8;
+            Rgb(if flip { [255 - v; 3] } else { [v; 3] })
+        }))
+    }
+
+    #[test]
+    fn test_similarity_survives_resize() {
+        let original = ImageHash::from_image(&gradient(256, 192, false));
+        let thumbnail = ImageHash::from_image(&gradient(100, 75, false));
+        let inverted = ImageHash::from_image(&gradient(256, 192, true));
+
+        assert_eq!(original.similarity(&original), 100.0);
+        assert!(original.similarity(&thumbnail) > 90.0);
+        assert!(original.similarity(&inverted) < 50.0);
+    }
+}
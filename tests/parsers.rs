@@ -0,0 +1,259 @@
+//! 使用 `tests/fixtures` 中保存的响应验证各引擎的解析逻辑，不访问网络
+
+use reverse_image_search::engines::{
+    Ascii2d, Google, GoogleLens, Iqdb, SauceNao, Soutubot, Yandex,
+};
+use reverse_image_search::{Error, MatchTier, Rating, SearchOptions};
+
+fn fixture(name: &str) -> String {
+    let path = format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name);
+    std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("读取 {} 失败: {}", path, e))
+}
+
+#[test]
+fn test_ascii2d() {
+    let html = fixture("ascii2d_color.html");
+    let results = Ascii2d::parse_results(&html).unwrap();
+
+    // 第一项是上传的图片，最后一项没有来源链接
+    assert_eq!(results.len(), 2);
+    let first = &results[0];
+    assert_eq!(first.url, "https://www.pixiv.net/artworks/81234567");
+    assert_eq!(first.title.as_deref(), Some("作品タイトル"));
+    assert_eq!(
+        first.index.as_deref(),
+        Some("a1b2c3d4e5f60718293a4b5c6d7e8f90")
+    );
+    assert_eq!(
+        first.thumbnail.as_deref(),
+        Some("https://ascii2d.net/thumbnail/a/1/b/2/a1b2c3d4e5f60718293a4b5c6d7e8f90.jpg")
+    );
+    let info = first.additional_info.as_ref().unwrap();
+    assert_eq!(info.author.as_deref(), Some("作者名"));
+    assert_eq!(
+        info.author_url.as_deref(),
+        Some("https://www.pixiv.net/users/1234567")
+    );
+    assert_eq!(
+        results[1].url,
+        "https://twitter.com/i/web/status/1234567890123456789"
+    );
+
+    assert_eq!(
+        Ascii2d::bovw_url(&html).as_deref(),
+        Some("https://ascii2d.net/search/bovw/58246f2cc1d3c9ba4f8a1c54a6d0d6b6")
+    );
+}
+
+#[test]
+fn test_iqdb() {
+    let response = Iqdb::parse_response(&fixture("iqdb.html"), "https://iqdb.org");
+
+    assert_eq!(response.results.len(), 2);
+    let best = &response.results[0];
+    assert_eq!(best.url, "https://danbooru.donmai.us/posts/4567890");
+    assert_eq!(best.similarity, Some(96.0));
+    assert_eq!(best.title.as_deref(), Some("[Danbooru] 2000×2828"));
+    assert_eq!(
+        best.thumbnail.as_deref(),
+        Some("https://iqdb.org/danbooru/1/2/12ab34cd56ef.jpg")
+    );
+    let info = best.additional_info.as_ref().unwrap();
+    assert_eq!(info.rating, Some(Rating::Safe));
+    assert_eq!(info.size, Some((2000, 2828)));
+    assert_eq!(info.tags, ["1girl", "blue_eyes", "long_hair", "solo"]);
+    assert_eq!(
+        info.ext_urls,
+        ["https://gelbooru.com/index.php?page=post&s=view&id=7654321"]
+    );
+    assert_eq!(info.post_id.as_deref(), Some("4567890"));
+    assert_eq!(info.match_tier, Some(MatchTier::Best));
+
+    let additional = &response.results[1];
+    assert_eq!(
+        additional.thumbnail.as_deref(),
+        Some("https://yande.re/thumb/aa/bb.jpg")
+    );
+    assert_eq!(
+        additional.additional_info.as_ref().unwrap().rating,
+        Some(Rating::Questionable)
+    );
+
+    assert_eq!(response.more_results.len(), 1);
+    assert_eq!(response.more_results[0].similarity, Some(58.0));
+    assert_eq!(response.more_url, None);
+}
+
+#[test]
+fn test_saucenao() {
+    let options = SearchOptions {
+        min_similarity: Some(50.0),
+        ..Default::default()
+    };
+    let results = SauceNao::parse_json(&fixture("saucenao.json"), &options).unwrap();
+
+    // 低于最低相似度的结果被过滤
+    assert_eq!(results.len(), 2);
+    let pixiv = &results[0];
+    assert_eq!(
+        pixiv.url,
+        "https://www.pixiv.net/member_illust.php?mode=medium&illust_id=81234567"
+    );
+    assert_eq!(pixiv.similarity, Some(94.52));
+    assert_eq!(pixiv.index.as_deref(), Some("5"));
+    assert_eq!(
+        pixiv.additional_info.as_ref().unwrap().author.as_deref(),
+        Some("作者名")
+    );
+
+    let danbooru = results[1].additional_info.as_ref().unwrap();
+    assert_eq!(
+        danbooru.ext_urls,
+        ["https://gelbooru.com/index.php?page=post&s=view&id=7654321"]
+    );
+    assert!(danbooru
+        .source_url
+        .as_deref()
+        .unwrap()
+        .contains("i.pximg.net"));
+
+    // status 大于 0 表示部分索引查询失败，其余结果照常返回
+    let partial = fixture("saucenao.json").replacen("\"status\":0", "\"status\":1", 1);
+    assert_eq!(SauceNao::parse_json(&partial, &options).unwrap().len(), 2);
+    let failed = r#"{"header":{"status":1,"message":"Index server offline"}}"#;
+    assert!(matches!(
+        SauceNao::parse_json(failed, &options),
+        Err(Error::Api { code: 1, .. })
+    ));
+
+    let limit = SauceNao::parse_json(&fixture("saucenao_limit.json"), &options);
+    assert!(matches!(
+        limit,
+        Err(Error::QuotaExhausted { engine: "SauceNAO" })
+    ));
+}
+
+#[test]
+fn test_soutubot() {
+    let search = Soutubot::parse_json(&fixture("soutubot.json")).unwrap();
+
+    assert_eq!(search.id, "2025020814015112");
+    assert_eq!(
+        search.page_url,
+        "https://soutubot.moe/results/2025020814015112"
+    );
+    assert_eq!(search.results.len(), 2);
+
+    let nhentai = &search.results[0];
+    assert_eq!(nhentai.url, "https://nhentai.net/g/123456");
+    assert_eq!(nhentai.similarity, Some(82.87));
+    let info = nhentai.additional_info.as_ref().unwrap();
+    assert_eq!(info.rating, Some(Rating::Explicit));
+    assert_eq!(info.language.as_deref(), Some("cn"));
+    assert_eq!(
+        info.page_url.as_deref(),
+        Some("https://nhentai.net/g/123456/12")
+    );
+
+    let ehentai = &search.results[1];
+    assert_eq!(ehentai.url, "https://e-hentai.org/g/2468013/a1b2c3d4e5");
+    let info = ehentai.additional_info.as_ref().unwrap();
+    assert_eq!(info.ext_urls, ["https://exhentai.org/g/2468013/a1b2c3d4e5"]);
+    assert_eq!(info.language.as_deref(), Some("jp"));
+    assert_eq!(info.page_url, None);
+}
+
+#[test]
+fn test_yandex() {
+    let response = Yandex::parse_html(&fixture("yandex.html")).unwrap();
+
+    assert_eq!(response.results.len(), 2);
+    let first = &response.results[0];
+    assert_eq!(first.url, "https://www.pixiv.net/en/artworks/81234567");
+    assert_eq!(
+        first.thumbnail.as_deref(),
+        Some("https://avatars.mds.yandex.net/i?id=aa11bb22&n=13")
+    );
+    let info = first.additional_info.as_ref().unwrap();
+    assert_eq!(info.size, Some((1200, 1697)));
+    assert_eq!(
+        info.source_url.as_deref(),
+        Some("https://i.pximg.net/img-master/img/2020/05/01/00/00/00/81234567_p0_master1200.jpg")
+    );
+    assert_eq!(info.site.as_deref(), Some("www.pixiv.net"));
+    assert_eq!(
+        info.snippet.as_deref(),
+        Some("An illustration of a girl with blue eyes.")
+    );
+
+    assert_eq!(response.similar_images.len(), 1);
+    assert_eq!(
+        response.similar_images[0]
+            .additional_info
+            .as_ref()
+            .unwrap()
+            .source_url
+            .as_deref(),
+        Some("https://example.com/a.jpg")
+    );
+    let tags: Vec<&str> = response.tags.iter().map(|t| t.text.as_str()).collect();
+    assert_eq!(tags, ["anime girl", "blue eyes"]);
+    assert_eq!(response.ocr_text.as_deref(), Some("サンプル"));
+    assert!(response.other_sizes.is_empty());
+}
+
+#[test]
+fn test_google() {
+    let url = "https://www.google.com/searchbyimage?image_url=x";
+    let response = Google::parse_response(&fixture("google.html"), url, 1).unwrap();
+
+    assert_eq!(response.best_guess.as_deref(), Some("hatsune miku"));
+    assert_eq!(response.results.len(), 3);
+    assert_eq!(
+        response.results[0].url,
+        "https://www.pixiv.net/en/artworks/81234567"
+    );
+    assert_eq!(
+        response.results[0].additional_info.as_ref().unwrap().size,
+        Some((2000, 2828))
+    );
+
+    assert_eq!(response.matching_pages.len(), 2);
+    let matching = &response.matching_pages[0];
+    assert_eq!(matching.url, "https://danbooru.donmai.us/posts/4567890");
+    assert_eq!(
+        matching.thumbnail.as_deref(),
+        Some("data:image/jpeg;base64,/9j/4AAQSkZJRgABAQAAAQABAAD=")
+    );
+
+    assert_eq!(response.similar_images.len(), 2);
+    assert_eq!(response.similar_images[0].url, "https://example.com/page1");
+    assert_eq!(
+        response.similar_images[1].thumbnail.as_deref(),
+        Some("https://encrypted-tbn0.gstatic.com/images?q=tbn:BBB2")
+    );
+    assert_eq!(
+        response.similar_url.as_deref(),
+        Some("https://www.google.com/search?tbs=simg:CAQSl&tbm=isch")
+    );
+
+    assert_eq!(response.pages.len(), 3);
+    assert_eq!(response.pages[0], url);
+}
+
+#[test]
+fn test_google_lens() {
+    let results = GoogleLens::parse_results(&fixture("google_lens.html")).unwrap();
+
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].url, "https://www.pixiv.net/en/artworks/81234567");
+    assert_eq!(
+        results[0].title.as_deref(),
+        Some("Blue-eyed girl illustration - pixiv")
+    );
+    let info = results[0].additional_info.as_ref().unwrap();
+    assert_eq!(info.site.as_deref(), Some("pixiv"));
+    assert_eq!(info.source_url, None);
+    let tags = &results[1].additional_info.as_ref().unwrap().tags;
+    assert_eq!(tags, &["价格: 1980", "货币: JPY"]);
+}
@@ -0,0 +1,108 @@
+//! 在本地模拟站点上验证各引擎在并发限制下的完整请求流程，不访问外部网络
+
+use reverse_image_search::engines::GoogleLens;
+use reverse_image_search::ratelimit::{set_rate_limit, RateLimit};
+use reverse_image_search::{ImageSearch, SearchOptions};
+use std::io::Cursor;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+fn png() -> Vec<u8> {
+    let image = image::RgbImage::from_pixel(16, 16, image::Rgb([200, 120, 40]));
+    let mut bytes = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut bytes), image::ImageFormat::Png)
+        .unwrap();
+    bytes
+}
+
+/// 读取完整的请求（含正文），返回请求行
+async fn read_request(socket: &mut TcpStream) -> String {
+    let mut buf = Vec::new();
+    let mut chunk = [0; 4096];
+    loop {
+        let n = socket.read(&mut chunk).await.unwrap();
+        if n == 0 {
+            break;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        let text = String::from_utf8_lossy(&buf);
+        if let Some(end) = text.find("\r\n\r\n") {
+            let length = text[..end]
+                .lines()
+                .find_map(|line| {
+                    let (name, value) = line.split_once(':')?;
+                    name.eq_ignore_ascii_case("content-length")
+                        .then(|| value.trim().parse::<usize>().ok())?
+                })
+                .unwrap_or(0);
+            if buf.len() >= end + 4 + length {
+                break;
+            }
+        }
+    }
+    let text = String::from_utf8_lossy(&buf);
+    text.lines().next().unwrap_or_default().to_string()
+}
+
+/// 模拟 Google Lens：上传返回 302，结果页返回保存的响应
+async fn serve_lens(listener: TcpListener) {
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    let html = std::fs::read_to_string(format!(
+        "{}/tests/fixtures/google_lens.html",
+        env!("CARGO_MANIFEST_DIR")
+    ))
+    .unwrap();
+    loop {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let (base, html) = (base.clone(), html.clone());
+        tokio::spawn(async move {
+            let request = read_request(&mut socket).await;
+            let response = if request.starts_with("POST /upload") {
+                format!(
+                    "HTTP/1.1 302 Found\r\nlocation: {}/search?ep=subb\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+                    base
+                )
+            } else if request.starts_with("GET /search") {
+                // 结果页稍慢返回，让两次上传的重定向响应同时存在
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                format!(
+                    "HTTP/1.1 200 OK\r\ncontent-type: text/html\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                    html.len(),
+                    html
+                )
+            } else {
+                "HTTP/1.1 200 OK\r\ncontent-length: 2\r\nconnection: close\r\n\r\nok".to_string()
+            };
+            let _ = socket.write_all(response.as_bytes()).await;
+        });
+    }
+}
+
+#[tokio::test]
+async fn test_google_lens_concurrent_uploads() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(serve_lens(listener));
+
+    // 两次上传同时进行时，各自的重定向响应不能占着名额等待结果页
+    set_rate_limit("Google Lens", RateLimit::unlimited().with_max_concurrent(2));
+    let lens = GoogleLens::new().with_base_url(&base);
+    let image = png();
+    let options = SearchOptions::default();
+    let (first, second) = tokio::time::timeout(
+        Duration::from_secs(10),
+        futures::future::join(
+            lens.search_bytes(&image, &options),
+            lens.search_bytes(&image, &options),
+        ),
+    )
+    .await
+    .expect("并发上传互相等待");
+
+    for (url, results) in [first.unwrap(), second.unwrap()] {
+        assert!(url.starts_with(&format!("{}/search?ep=subb&qsubts=", base)));
+        assert_eq!(results.len(), 2);
+    }
+}
@@ -0,0 +1,112 @@
+//! 联网重新录制 `tests/cassettes` 中的磁带与 `tests/fixtures` 中的响应，默认不运行：
+//! `cargo test --test record -- --ignored`
+//!
+//! 录制会覆盖同名文件；请求中的凭据与响应中的 `Set-Cookie` 值会被替换为 `REDACTED`，
+//! `SAUCENAO_API_KEY` 与 `YANDEX_COOKIE` 中的值也会从响应正文中去掉，
+//! 提交前仍需检查正文中是否有个人信息。录制后按新的响应更新 `tests/replay.rs` 与 `tests/parsers.rs` 中的断言
+
+use reverse_image_search::cassette::{self, Cassette};
+use reverse_image_search::engines::{
+    Ascii2d, Google, GoogleLens, Iqdb, SauceNao, Soutubot, Yandex,
+};
+use reverse_image_search::{ImageSearch, SearchOptions};
+use std::sync::Arc;
+
+/// 录制使用的公开测试图片
+const IMAGE_URL: &str =
+    "https://raw.githubusercontent.com/kitUIN/PicImageSearch/main/demo/test.jpg";
+
+fn record(name: &str) -> Arc<Cassette> {
+    let path = format!("{}/tests/cassettes/{}", env!("CARGO_MANIFEST_DIR"), name);
+    Arc::new(Cassette::record(path))
+}
+
+#[tokio::test]
+#[ignore = "需要网络访问 ASCII2D"]
+async fn record_ascii2d() {
+    let tape = record("ascii2d.json");
+    let ascii2d = Ascii2d::new();
+    let options = SearchOptions::default();
+    let search = ascii2d.search_url(IMAGE_URL, &options);
+    let (_, results) = cassette::scope("ASCII2D", tape, search).await.unwrap();
+    assert!(!results.is_empty());
+}
+
+#[tokio::test]
+#[ignore = "需要网络访问 Google Lens"]
+async fn record_google_lens() {
+    let image = reqwest::get(IMAGE_URL)
+        .await
+        .unwrap()
+        .bytes()
+        .await
+        .unwrap();
+    let tape = record("google_lens.json");
+    let lens = GoogleLens::new();
+    let options = SearchOptions::default();
+    let search = lens.search_bytes(&image, &options);
+    let (_, results) = cassette::scope("Google Lens", tape, search).await.unwrap();
+    assert!(!results.is_empty());
+}
+
+/// 用环境变量提供的凭据搜索一次，把 URL 含有 `url_part` 的响应正文保存为 `tests/fixtures/{name}`
+async fn record_fixture(engine: &dyn ImageSearch, url_part: &str, name: &str) {
+    let dir = tempfile::tempdir().unwrap();
+    let tape = Arc::new(Cassette::record(dir.path().join("tape.json")));
+    let options = SearchOptions::default();
+    let search = engine.search_url(IMAGE_URL, &options);
+    cassette::scope(engine.name(), Arc::clone(&tape), search)
+        .await
+        .unwrap();
+
+    let recorded: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(tape.path()).unwrap()).unwrap();
+    let body = recorded["interactions"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|interaction| &interaction["response"])
+        .find(|response| response["url"].as_str().unwrap().contains(url_part))
+        .and_then(|response| response["body"].as_str())
+        .unwrap_or_else(|| panic!("没有录制到 {} 的响应", url_part));
+
+    let path = format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name);
+    std::fs::write(path, sanitize(body)).unwrap();
+}
+
+/// 去掉正文中出现的 API key 与 cookie 值
+fn sanitize(body: &str) -> String {
+    let mut secrets: Vec<String> = std::env::var("SAUCENAO_API_KEY").into_iter().collect();
+    if let Ok(cookies) = std::env::var("YANDEX_COOKIE") {
+        secrets.extend(
+            cookies
+                .split(';')
+                .filter_map(|pair| pair.split_once('='))
+                .map(|(_, value)| value.trim().to_string()),
+        );
+    }
+    secrets
+        .iter()
+        .filter(|secret| !secret.is_empty())
+        .fold(body.to_string(), |body, secret| {
+            body.replace(secret.as_str(), "REDACTED")
+        })
+}
+
+#[tokio::test]
+#[ignore = "需要网络访问各搜索引擎"]
+async fn record_fixtures() {
+    let api_key = std::env::var("SAUCENAO_API_KEY").ok();
+    record_fixture(&Ascii2d::new(), "/search/color/", "ascii2d_color.html").await;
+    record_fixture(&Google::new(), "google.com/search", "google.html").await;
+    record_fixture(
+        &GoogleLens::new(),
+        "lens.google.com/search",
+        "google_lens.html",
+    )
+    .await;
+    record_fixture(&Iqdb::new(), "iqdb.org", "iqdb.html").await;
+    record_fixture(&SauceNao::new(api_key), "search.php", "saucenao.json").await;
+    record_fixture(&Soutubot::new(), "/api/search", "soutubot.json").await;
+    record_fixture(&Yandex::from_env(), "/images/search", "yandex.html").await;
+}
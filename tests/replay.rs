@@ -0,0 +1,158 @@
+//! 回放 `tests/cassettes` 中的磁带，离线验证各引擎完整的请求流程
+//!
+//! 目前的磁带是按解析测试的响应手工整理的，还没有用真实请求录制过；
+//! 重新录制见 `tests/record.rs`，录制后需按新的响应更新这里的请求地址与断言
+
+use futures::TryStreamExt;
+use reverse_image_search::cassette::{self, Cassette};
+use reverse_image_search::engines::google::PageLimit;
+use reverse_image_search::engines::iqdb::{IqdbOptions, IqdbService};
+use reverse_image_search::engines::{Ascii2d, Google, GoogleLens, Iqdb, Soutubot};
+use reverse_image_search::{Error, ImageSearch, SearchOptions};
+use std::io::Cursor;
+use std::sync::Arc;
+
+fn load(name: &str) -> Arc<Cassette> {
+    let path = format!("{}/tests/cassettes/{}", env!("CARGO_MANIFEST_DIR"), name);
+    Arc::new(Cassette::replay(path).unwrap())
+}
+
+fn png() -> Vec<u8> {
+    let image = image::RgbImage::from_pixel(16, 16, image::Rgb([200, 120, 40]));
+    let mut bytes = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut bytes), image::ImageFormat::Png)
+        .unwrap();
+    bytes
+}
+
+#[tokio::test]
+async fn test_ascii2d_color_then_bovw() {
+    let tape = load("ascii2d.json");
+
+    // 访问主页获取 cookie，色彩搜索后再请求特征搜索
+    let ascii2d = Ascii2d::new();
+    let options = SearchOptions::default();
+    let search = ascii2d.search_url("https://example.com/image.jpg", &options);
+    let (_, results) = cassette::scope("ASCII2D", Arc::clone(&tape), search)
+        .await
+        .unwrap();
+    assert_eq!(tape.remaining(), 0);
+
+    let urls: Vec<&str> = results.iter().map(|r| r.url.as_str()).collect();
+    assert_eq!(
+        urls,
+        [
+            "https://www.pixiv.net/artworks/81234567",
+            "https://twitter.com/i/web/status/1234567890123456789",
+            "https://www.pixiv.net/artworks/92345678",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_google_lens_redirect() {
+    let tape = load("google_lens.json");
+
+    // 上传返回 302，随后带上时间戳参数请求 Location 指向的结果页
+    let lens = GoogleLens::new();
+    let image = png();
+    let options = SearchOptions::default();
+    let (url, results) = cassette::scope(
+        "Google Lens",
+        Arc::clone(&tape),
+        lens.search_bytes(&image, &options),
+    )
+    .await
+    .unwrap();
+    assert_eq!(tape.remaining(), 0);
+
+    assert!(url.starts_with("https://lens.google.com/search?ep=subb"));
+    assert!(url.contains("&qsubts="));
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].url, "https://www.pixiv.net/en/artworks/81234567");
+}
+
+#[tokio::test]
+async fn test_google_paginate_limits() {
+    let html = std::fs::read_to_string(format!(
+        "{}/tests/fixtures/google.html",
+        env!("CARGO_MANIFEST_DIR")
+    ))
+    .unwrap();
+    let url = "https://www.google.com/searchbyimage?image_url=https://example.com/image.jpg";
+    let first = || Google::parse_response(&html, url, 1).unwrap();
+    let google = Google::new();
+
+    // 页数上限为 1 时不会请求下一页
+    let tape = load("google_pages.json");
+    let pages: Vec<_> = cassette::scope(
+        "Google",
+        Arc::clone(&tape),
+        google.paginate(first(), PageLimit::pages(1)).try_collect(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(pages.len(), 1);
+    assert_eq!(tape.remaining(), 1);
+
+    // 首页 3 条，第二页截断到 1 条后达到上限，不再请求第三页
+    let tape = load("google_pages.json");
+    let pages: Vec<_> = cassette::scope(
+        "Google",
+        Arc::clone(&tape),
+        google
+            .paginate(first(), PageLimit::results(4))
+            .try_collect(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(tape.remaining(), 0);
+    let counts: Vec<usize> = pages.iter().map(|page| page.results.len()).collect();
+    assert_eq!(counts, [3, 1]);
+    assert_eq!(pages[1].current_page, 2);
+    // 第二页指回首页与第三页的链接写法不同，不会重复追加
+    assert_eq!(pages[1].pages.len(), 3);
+    assert_eq!(pages[1].results[0].url, "https://example.com/page2/a");
+}
+
+#[tokio::test]
+async fn test_soutubot_rejects_invalid_factor() {
+    // 无效的系数在发出请求之前就被拒绝，空磁带不会被访问
+    let tape = load("empty.json");
+    let image = png();
+    let options = SearchOptions::default();
+    for factor in [0.0, -1.2, f32::NAN, f32::INFINITY] {
+        let soutubot = Soutubot::new().with_factor(factor);
+        let search = soutubot.search_bytes_page(&image, &options);
+        let result = cassette::scope("Soutubot", Arc::clone(&tape), search).await;
+        assert!(matches!(result, Err(Error::InvalidArgument(_))));
+
+        let research = soutubot.research("2025020814015112", factor, &options);
+        let result = cassette::scope("Soutubot", Arc::clone(&tape), research).await;
+        assert!(matches!(result, Err(Error::InvalidArgument(_))));
+    }
+}
+
+#[tokio::test]
+async fn test_iqdb_rejects_mixed_services() {
+    // 3D 数据源与普通数据源不在同一站点，混用时不发出请求
+    let tape = load("empty.json");
+    let iqdb = Iqdb::new().with_options(IqdbOptions {
+        services: vec![IqdbService::Danbooru, IqdbService::ThreeDBooru],
+        ..Default::default()
+    });
+    let search = iqdb.search_url_page("https://example.com/a.jpg");
+    let result = cassette::scope("IQDB", Arc::clone(&tape), search).await;
+    assert!(matches!(result, Err(Error::InvalidArgument(_))));
+
+    let iqdb = Iqdb::new().with_options(IqdbOptions {
+        services: vec![IqdbService::Danbooru],
+        three_d: true,
+        ..Default::default()
+    });
+    let image = png();
+    let search = iqdb.search_bytes_page(&image);
+    let result = cassette::scope("IQDB", Arc::clone(&tape), search).await;
+    assert!(matches!(result, Err(Error::InvalidArgument(_))));
+}